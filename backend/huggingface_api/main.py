@@ -80,6 +80,10 @@ async def predict(file: UploadFile = File(...)):
             top_class_id = result.probs.top1
             top_class_name = result.names[top_class_id]
             confidence = float(result.probs.top1conf)
+            # Full class distribution so clients can show close alternatives
+            probabilities = {
+                result.names[i]: float(p) for i, p in enumerate(result.probs.data.tolist())
+            }
         else:
             # Object detection result - use the highest confidence box
             if len(result.boxes) == 0:
@@ -90,12 +94,14 @@ async def predict(file: UploadFile = File(...)):
             top_class_id = int(result.boxes.cls[best_box_idx].item())
             top_class_name = result.names[top_class_id]
             confidence = float(result.boxes.conf[best_box_idx].item())
+            probabilities = {top_class_name: confidence}
         
         process_time = time.time() - start_time
         
         return {
             "label": top_class_name,
             "confidence": confidence,
+            "probabilities": probabilities,
             "processing_time_ms": int(process_time * 1000)
         }
         
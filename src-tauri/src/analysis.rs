@@ -0,0 +1,477 @@
+use serde::{Serialize, Deserialize};
+use crate::calibration;
+use crate::classifier::{AnalysisError, Classifier, ImageInput, Prediction};
+use crate::database::{CachePolicy, Calibration, DbConnection, NewAnalysis, ResolveError, Species};
+use crate::fusion::{self, AnatomyCheck, AnatomyFusion, AnatomyObservations};
+use crate::offline_queue;
+use crate::open_set::{self, OpenSetDecision, OpenSetSettings, Outcome, UNKNOWN_LABEL};
+use crate::preprocess::{self, PreprocessInfo};
+use crate::quality::{self, QualityMode, QualityReport, QualitySettings};
+use crate::settings::AppSettings;
+use crate::tiling::{self, TilingReport, TilingSettings};
+use image::DynamicImage;
+use std::collections::HashMap;
+
+/// Number of ranked alternatives returned when the caller does not ask for a specific count
+pub const DEFAULT_TOP_K: usize = 5;
+
+/// Per-request analysis settings
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    pub top_k: usize,
+    /// Skip the prediction cache lookup; the fresh result still replaces the cached one
+    pub bypass_cache: bool,
+    pub cache_policy: CachePolicy,
+    pub quality: QualitySettings,
+    /// Classify overlapping tiles and aggregate their votes when `enabled`
+    pub tiling: TilingSettings,
+    /// Per-label thresholds below which the result is reported as unknown
+    pub open_set: OpenSetSettings,
+    /// Characters recorded by hand; when present the predictions are re-ranked by consistency with them
+    pub observations: Option<AnatomyObservations>,
+}
+
+impl AnalysisOptions {
+    /// Options for a request made with the user's current settings
+    pub fn from_settings(settings: &AppSettings) -> Self {
+        AnalysisOptions {
+            cache_policy: settings.cache,
+            quality: settings.quality.clone(),
+            tiling: settings.tiling.clone(),
+            open_set: settings.open_set.clone(),
+            ..AnalysisOptions::default()
+        }
+    }
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            top_k: DEFAULT_TOP_K,
+            bypass_cache: false,
+            cache_policy: CachePolicy::default(),
+            quality: QualitySettings::default(),
+            tiling: TilingSettings::default(),
+            open_set: OpenSetSettings::default(),
+            observations: None,
+        }
+    }
+}
+
+/// One entry of the ranked prediction list, joined to its species record when one exists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedPrediction {
+    pub rank: usize,
+    pub label: String,
+    pub probability: f64,
+    pub species: Option<Species>,
+    /// Consistency with the recorded observations; absent when none were given
+    pub anatomy: Option<AnatomyCheck>,
+}
+
+/// Result of analyzing a single image, as returned to the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisResult {
+    /// Top label, or `unknown` when it did not clear its confidence threshold
+    pub label: String,
+    /// Calibrated when a calibration has been fitted for the model; the combined probability when
+    /// observations moved another prediction to the top
+    pub confidence: f64,
+    /// Uncalibrated backend confidence; present only when a calibration was applied
+    pub raw_confidence: Option<f64>,
+    #[serde(rename = "_fallback")]
+    pub fallback: bool,
+    pub predictions: Vec<RankedPrediction>,
+    pub outcome: Outcome,
+    /// Threshold check applied to the top label; absent when open-set rejection is disabled
+    pub open_set: Option<OpenSetDecision>,
+    /// Whether the backend prediction was served from the local cache
+    pub cached: bool,
+    /// How the image was normalized before classification; absent for cached results
+    pub preprocessing: Option<PreprocessInfo>,
+    /// Sharpness, exposure and resolution checks; absent when the quality gate is off
+    pub quality: Option<QualityReport>,
+    /// Per-tile votes and agreement; present only for tiled analyses
+    pub tiling: Option<TilingReport>,
+    /// How the observations re-ranked the predictions; absent when none were given
+    pub anatomy: Option<AnatomyFusion>,
+    /// Id of the stored history record, when the result was saved
+    pub analysis_id: Option<i64>,
+}
+
+impl AnalysisResult {
+    /// Keep the `top_k` most probable classes and attach species information to each
+    pub fn from_prediction(prediction: Prediction, top_k: usize, db: Option<&DbConnection>) -> Self {
+        let predictions = prediction.probabilities
+            .into_iter()
+            .take(top_k.max(1))
+            .enumerate()
+            .map(|(index, score)| {
+                let species = db.and_then(|db| species_for_label(db, &score.label));
+                RankedPrediction {
+                    rank: index + 1,
+                    label: score.label,
+                    probability: score.probability,
+                    species,
+                    anatomy: None,
+                }
+            })
+            .collect();
+
+        AnalysisResult {
+            label: prediction.label,
+            confidence: prediction.confidence,
+            raw_confidence: None,
+            fallback: prediction.fallback,
+            predictions,
+            outcome: Outcome::Identified,
+            open_set: None,
+            cached: false,
+            preprocessing: None,
+            quality: None,
+            tiling: None,
+            anatomy: None,
+            analysis_id: None,
+        }
+    }
+
+    /// Report the result as unknown when the top label falls below its threshold.
+    /// The ranked predictions are kept so the closest known species can still be shown.
+    pub fn apply_open_set(&mut self, thresholds: &HashMap<String, f64>, settings: &OpenSetSettings) {
+        let runner_up = self.predictions.get(1).map(|prediction| prediction.probability);
+        let decision = open_set::decide(&self.label, self.confidence, runner_up, thresholds, settings);
+
+        if decision.outcome == Outcome::Unknown {
+            self.label = UNKNOWN_LABEL.to_string();
+        }
+        self.outcome = decision.outcome;
+        self.open_set = Some(decision);
+    }
+
+    /// Save the result in the analysis history and remember the record id
+    pub fn record(&mut self, db: &DbConnection, image_path: &str, image_hash: &str, backend: &str) {
+        let species_id = self.predictions.first()
+            .filter(|top| top.label == self.label)
+            .and_then(|top| top.species.as_ref())
+            .map(|species| species.id);
+
+        let result = match serde_json::to_value(&*self) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Failed to serialize analysis result: {}", e);
+                return;
+            }
+        };
+
+        let analysis = NewAnalysis {
+            image_path,
+            image_hash,
+            label: &self.label,
+            confidence: self.confidence,
+            species_id,
+            backend,
+            result: &result,
+        };
+
+        match db.insert_analysis(&analysis) {
+            Ok(id) => self.analysis_id = Some(id),
+            Err(e) => eprintln!("Failed to save analysis history: {}", e),
+        }
+    }
+}
+
+/// Load an image, classify it and attach species records to the ranked predictions.
+/// When the classifier is unreachable the image is queued and `AnalysisError::Queued` is returned.
+pub async fn analyze(
+    file_path: &str,
+    classifier: &dyn Classifier,
+    db: Option<&DbConnection>,
+    options: &AnalysisOptions,
+) -> Result<AnalysisResult, AnalysisError> {
+    println!("Analyzing local image: {}", file_path);
+
+    let image = ImageInput::from_path(file_path)?;
+    let image_hash = image.content_hash();
+
+    let (decoded, quality) = match inspect_image(&image, &options.quality).await {
+        Ok((decoded, orientation, quality)) => (Some((decoded, orientation)), quality),
+        // Skip preprocessing, the quality checks and tiling, which all need the decoded pixels
+        Err(AnalysisError::UnsupportedFormat(reason)) if classifier.accepts_undecoded_images() => {
+            eprintln!("Sending {} undecoded: {}", file_path, reason);
+            (None, None)
+        },
+        Err(e) => return Err(e),
+    };
+    let tiled = options.tiling.enabled && decoded.is_some();
+    let model_version = cache_key(classifier, options, tiled);
+
+    if let Some(report) = &quality {
+        if !report.passed && options.quality.mode == QualityMode::Reject {
+            return Err(AnalysisError::QualityRejected(report.issues.clone()));
+        }
+    }
+
+    let cached = match db {
+        Some(db) if !options.bypass_cache => cached_prediction(db, &image_hash, &model_version, &options.cache_policy),
+        _ => None,
+    };
+
+    let from_cache = cached.is_some();
+    let (prediction, preprocessing, tiling) = match cached {
+        Some((prediction, tiling)) => (prediction, None, tiling),
+        None => {
+            let outcome = match decoded {
+                Some((decoded, _)) if tiled => tiling::classify_tiled(classifier, &image, decoded, &options.tiling).await
+                    .map(|(prediction, report)| (prediction, None, Some(report))),
+                Some((decoded, orientation)) => classify_whole(classifier, &image, decoded, orientation).await
+                    .map(|(prediction, preprocessing)| (prediction, Some(preprocessing), None)),
+                None => classifier.classify(&image).await
+                    .map(|prediction| (prediction, None, None)),
+            };
+
+            let outcome = match outcome {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    eprintln!("Analysis failed ({}): {}", e.kind(), e);
+                    // Keep the request for later instead of failing when the endpoint is unreachable
+                    return Err(match db {
+                        Some(db) if e.is_unreachable() => offline_queue::enqueue(db, file_path, &image, options),
+                        _ => e,
+                    });
+                }
+            };
+
+            if let Some(db) = db {
+                cache_prediction(db, &image_hash, &model_version, &outcome.0, outcome.2.as_ref(), &options.cache_policy);
+            }
+            outcome
+        },
+    };
+
+    // The cache holds raw predictions, so a refitted calibration also applies to cached results.
+    // Calibrations are fitted on whole-image predictions; averaged tile probabilities are spread
+    // differently, so tiled results are reported uncalibrated.
+    let calibration = match db {
+        Some(db) if !prediction.fallback && !tiled => load_calibration(db, &classifier.model_version()),
+        _ => None,
+    };
+    let raw_confidence = prediction.confidence;
+    let prediction = match &calibration {
+        Some(calibration) => calibration::apply(&prediction, calibration),
+        None => prediction,
+    };
+
+    let mut result = AnalysisResult::from_prediction(prediction, options.top_k, db);
+    result.cached = from_cache;
+    result.raw_confidence = calibration.map(|_| raw_confidence);
+    result.preprocessing = preprocessing;
+    result.tiling = tiling;
+
+    if options.open_set.enabled {
+        let thresholds = db.map(label_thresholds).unwrap_or_default();
+        result.apply_open_set(&thresholds, &options.open_set);
+    }
+
+    // After the open-set check, which judges the model's own confidence
+    if let (Some(db), Some(observations)) = (db, &options.observations) {
+        if !observations.is_empty() {
+            fusion::apply(&mut result, observations, db);
+        }
+    }
+
+    result.quality = quality;
+    if let Some(db) = db {
+        result.record(db, file_path, &image_hash, classifier.name());
+    }
+
+    Ok(result)
+}
+
+/// Decode the image upright and run the quality checks, off the async executor since large TIFFs are CPU bound
+async fn inspect_image(image: &ImageInput, settings: &QualitySettings) -> Result<(DynamicImage, u32, Option<QualityReport>), AnalysisError> {
+    let data = image.data.clone();
+    let settings = settings.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let (decoded, orientation) = preprocess::decode_upright(&data)?;
+        let report = match settings.mode {
+            QualityMode::Off => None,
+            _ => Some(quality::assess(&decoded, &settings)),
+        };
+        Ok((decoded, orientation, report))
+    })
+    .await
+    .map_err(|e| AnalysisError::Backend(format!("Image inspection task failed: {}", e)))?
+}
+
+/// Normalize the whole image and classify it in a single request
+async fn classify_whole(classifier: &dyn Classifier, image: &ImageInput, decoded: DynamicImage, orientation: u32) -> Result<(Prediction, PreprocessInfo), AnalysisError> {
+    let (prepared, preprocessing) = prepare_image(image, decoded, orientation, classifier.input_size()).await?;
+    let prediction = classifier.classify(&prepared).await?;
+    Ok((prediction, preprocessing))
+}
+
+async fn prepare_image(image: &ImageInput, decoded: DynamicImage, orientation: u32, max_dimension: Option<u32>) -> Result<(ImageInput, PreprocessInfo), AnalysisError> {
+    let image = image.clone();
+    tauri::async_runtime::spawn_blocking(move || preprocess::prepare(&image, decoded, orientation, max_dimension))
+        .await
+        .map_err(|e| AnalysisError::Backend(format!("Preprocessing task failed: {}", e)))?
+}
+
+fn label_thresholds(db: &DbConnection) -> HashMap<String, f64> {
+    db.label_thresholds().unwrap_or_else(|e| {
+        eprintln!("Failed to load label thresholds: {}", e);
+        HashMap::new()
+    })
+}
+
+/// Species record for a label; ambiguous labels are logged rather than guessed
+pub fn species_for_label(db: &DbConnection, label: &str) -> Option<Species> {
+    match db.get_species_by_label(label) {
+        Ok(species) => Some(species),
+        Err(ResolveError::NotFound(_)) => None,
+        Err(e) => {
+            eprintln!("Failed to resolve species for {}: {}", label, e);
+            None
+        }
+    }
+}
+
+fn load_calibration(db: &DbConnection, model_version: &str) -> Option<Calibration> {
+    db.calibration(model_version).unwrap_or_else(|e| {
+        eprintln!("Failed to load calibration: {}", e);
+        None
+    })
+}
+
+/// Cache entries are keyed by everything that changes the raw prediction for the same image bytes:
+/// the model, the size images are scaled to before classification and whether tiles were voted on
+fn cache_key(classifier: &dyn Classifier, options: &AnalysisOptions, tiled: bool) -> String {
+    let mut key = classifier.model_version();
+    if let Some(input_size) = classifier.input_size() {
+        key.push_str(&format!("|input:{}", input_size));
+    }
+    if tiled {
+        key.push_str(&format!("|{}", options.tiling.cache_key()));
+    }
+    key
+}
+
+/// Cache entry: the raw backend prediction, with the tile votes it was aggregated from when tiled.
+/// Flattened so entries written before tiling reports were cached still read back.
+#[derive(Serialize, Deserialize)]
+struct CachedPrediction {
+    #[serde(flatten)]
+    prediction: Prediction,
+    #[serde(default)]
+    tiling: Option<TilingReport>,
+}
+
+fn cached_prediction(db: &DbConnection, image_hash: &str, model_version: &str, policy: &CachePolicy) -> Option<(Prediction, Option<TilingReport>)> {
+    match db.cached_prediction(image_hash, model_version, policy) {
+        Ok(Some(prediction_json)) => serde_json::from_str::<CachedPrediction>(&prediction_json).ok()
+            .map(|cached| (cached.prediction, cached.tiling)),
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("Prediction cache lookup failed: {}", e);
+            None
+        }
+    }
+}
+
+fn cache_prediction(db: &DbConnection, image_hash: &str, model_version: &str, prediction: &Prediction, tiling: Option<&TilingReport>, policy: &CachePolicy) {
+    // Mock results must never be served as if they were real
+    if prediction.fallback {
+        return;
+    }
+
+    let cached = CachedPrediction { prediction: prediction.clone(), tiling: tiling.cloned() };
+    let stored = serde_json::to_string(&cached)
+        .map_err(|e| e.to_string())
+        .and_then(|prediction_json| db.cache_prediction(image_hash, model_version, &prediction_json, policy)
+            .map_err(|e| e.to_string()));
+
+    if let Err(e) = stored {
+        eprintln!("Failed to cache prediction: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::{ClassScore, StubClassifier};
+
+    #[test]
+    fn cache_entries_without_a_tiling_report_still_read() {
+        let cached: CachedPrediction = serde_json::from_str(
+            r#"{"label": "Toona", "confidence": 0.9, "probabilities": [{"label": "Toona", "probability": 0.9}]}"#
+        ).unwrap();
+        assert_eq!(cached.prediction.label, "Toona");
+        assert!(cached.tiling.is_none());
+    }
+
+    #[test]
+    fn keeps_the_top_k_classes_in_order_with_their_species() {
+        let db = DbConnection::in_memory();
+        db.import_species_file(concat!(env!("CARGO_MANIFEST_DIR"), "/../backend/species_data.json")).unwrap();
+
+        // Out of order, and more classes than are kept
+        let scores = [("Swietenia_mahagoni_Mahogany", 0.1), ("Toona_ciliata_Toon", 0.4), ("Unlisted_class", 0.25),
+            ("Tectona_grandis_Segun", 0.2), ("Gmelina_arborea_Gamar", 0.05)];
+        let prediction = Prediction::from_distribution(
+            scores.iter().map(|(label, probability)| ClassScore { label: label.to_string(), probability: *probability }).collect()
+        ).unwrap();
+
+        let result = AnalysisResult::from_prediction(prediction, 3, Some(&db));
+
+        assert_eq!((result.label.as_str(), result.confidence), ("Toona_ciliata_Toon", 0.4));
+        let ranked: Vec<(usize, &str, f64, Option<&str>)> = result.predictions.iter()
+            .map(|ranked| (ranked.rank, ranked.label.as_str(), ranked.probability, ranked.species.as_ref().map(|species| species.scientific_name.as_str())))
+            .collect();
+        assert_eq!(ranked, vec![
+            (1, "Toona_ciliata_Toon", 0.4, Some("Toona ciliata")),
+            (2, "Unlisted_class", 0.25, None),
+            (3, "Tectona_grandis_Segun", 0.2, Some("Tectona grandis")),
+        ]);
+
+        // At least the top class is always kept, and no database means no species
+        let prediction = Prediction::from_distribution(vec![
+            ClassScore { label: "Toona_ciliata_Toon".to_string(), probability: 0.7 },
+            ClassScore { label: "Tectona_grandis_Segun".to_string(), probability: 0.3 },
+        ]).unwrap();
+        let result = AnalysisResult::from_prediction(prediction, 0, None);
+        assert_eq!(result.predictions.len(), 1);
+        assert!(result.predictions[0].species.is_none());
+    }
+
+    #[test]
+    fn undecodable_images_go_to_backends_that_decode_them() {
+        let path = std::env::temp_dir().join(format!("treescope-analysis-{}-undecodable.tiff", std::process::id()));
+        std::fs::write(&path, b"not a tiff the image crate reads").unwrap();
+        let file_path = path.to_string_lossy().to_string();
+
+        let mut options = AnalysisOptions::default();
+        options.quality.mode = QualityMode::Reject;
+        options.tiling.enabled = true;
+
+        let local = StubClassifier::new("local", &["Toona", "Tectona"]);
+        let rejected = tauri::async_runtime::block_on(analyze(&file_path, &local, None, &options));
+
+        let mut remote = StubClassifier::new("remote", &["Toona", "Tectona"]);
+        remote.accepts_undecoded = true;
+        let result = tauri::async_runtime::block_on(analyze(&file_path, &remote, None, &options));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(rejected, Err(AnalysisError::UnsupportedFormat(_))));
+        assert!(local.received.lock().unwrap().is_empty());
+
+        let result = result.unwrap();
+        assert_eq!(result.label, "Toona");
+        assert!(result.preprocessing.is_none() && result.quality.is_none() && result.tiling.is_none());
+        // One request with the file exactly as read, not tiles or a re-encoded copy
+        let received = remote.received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].data, b"not a tiff the image crate reads");
+        assert_eq!(received[0].mime_type, "image/tiff");
+    }
+}
@@ -0,0 +1,211 @@
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+use crate::analysis::{self, AnalysisOptions, AnalysisResult};
+use crate::classifier::{AnalysisError, Classifier, SUPPORTED_EXTENSIONS};
+use crate::database::{DbConnection, Species};
+
+/// Emitted after each image in a batch finishes
+pub const BATCH_PROGRESS_EVENT: &str = "analysis-batch-progress";
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_CONCURRENCY: usize = 16;
+
+/// Outcome for a single image of a batch
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItem {
+    pub image_path: String,
+    pub result: Option<AnalysisResult>,
+    pub error: Option<AnalysisError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub completed: usize,
+    pub total: usize,
+    pub item: BatchItem,
+}
+
+/// How many images were identified as one label, with its species record
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeciesTally {
+    pub label: String,
+    pub count: usize,
+    pub species: Option<Species>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub queued: usize,
+    pub species: Vec<SpeciesTally>,
+    pub items: Vec<BatchItem>,
+}
+
+/// Supported image files directly inside `directory`, sorted by name
+pub fn collect_images(directory: &str) -> Result<Vec<String>, String> {
+    let entries = fs::read_dir(directory)
+        .map_err(|e| format!("Failed to read directory {}: {}", directory, e))?;
+
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_supported_image(path))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    paths.sort();
+    Ok(paths)
+}
+
+fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Analyze `paths` with at most `concurrency` requests in flight, emitting progress after each image
+pub async fn analyze_batch(
+    app_handle: &AppHandle,
+    paths: Vec<String>,
+    classifier: &dyn Classifier,
+    db: Option<&DbConnection>,
+    options: &AnalysisOptions,
+    concurrency: usize,
+) -> BatchSummary {
+    let total = paths.len();
+    let concurrency = concurrency.clamp(1, MAX_CONCURRENCY);
+
+    let mut completions = stream::iter(paths.into_iter().enumerate())
+        .map(|(index, image_path)| async move {
+            let outcome = analysis::analyze(&image_path, classifier, db, options).await;
+            let item = match outcome {
+                Ok(result) => BatchItem { image_path, result: Some(result), error: None },
+                Err(e) => BatchItem { image_path, result: None, error: Some(e) },
+            };
+            (index, item)
+        })
+        .buffer_unordered(concurrency);
+
+    let mut items: Vec<Option<BatchItem>> = vec![None; total];
+    let mut completed = 0;
+
+    while let Some((index, item)) = completions.next().await {
+        completed += 1;
+
+        let progress = BatchProgress { completed, total, item: item.clone() };
+        if let Err(e) = app_handle.emit_all(BATCH_PROGRESS_EVENT, progress) {
+            eprintln!("Failed to emit {}: {}", BATCH_PROGRESS_EVENT, e);
+        }
+
+        items[index] = Some(item);
+    }
+
+    // Report items in the order they were requested
+    let items: Vec<BatchItem> = items.into_iter().flatten().collect();
+    summarize(items, db)
+}
+
+fn summarize(items: Vec<BatchItem>, db: Option<&DbConnection>) -> BatchSummary {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut queued = 0;
+    let mut failed = 0;
+
+    for item in &items {
+        match (&item.result, &item.error) {
+            (Some(result), _) => *counts.entry(result.label.clone()).or_insert(0) += 1,
+            (None, Some(AnalysisError::Queued { .. })) => queued += 1,
+            _ => failed += 1,
+        }
+    }
+
+    let mut species: Vec<SpeciesTally> = counts.into_iter()
+        .map(|(label, count)| SpeciesTally {
+            species: db.and_then(|db| analysis::species_for_label(db, &label)),
+            label,
+            count,
+        })
+        .collect();
+    species.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.label.cmp(&b.label)));
+
+    BatchSummary {
+        total: items.len(),
+        succeeded: items.len() - queued - failed,
+        failed,
+        queued,
+        species,
+        items,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::{ClassScore, Prediction};
+
+    fn identified(image_path: &str, label: &str) -> BatchItem {
+        let prediction = Prediction::from_distribution(vec![ClassScore { label: label.to_string(), probability: 0.9 }]).unwrap();
+        BatchItem {
+            image_path: image_path.to_string(),
+            result: Some(AnalysisResult::from_prediction(prediction, 1, None)),
+            error: None,
+        }
+    }
+
+    fn errored(image_path: &str, error: AnalysisError) -> BatchItem {
+        BatchItem { image_path: image_path.to_string(), result: None, error: Some(error) }
+    }
+
+    #[test]
+    fn tallies_outcomes_and_labels() {
+        let items = vec![
+            identified("a.jpg", "Toona ciliata_Toon"),
+            errored("b.jpg", AnalysisError::Queued { queue_id: 1 }),
+            identified("c.jpg", "Chukrasia tabularis_Chickrasi"),
+            errored("d.jpg", AnalysisError::Backend("bad response".to_string())),
+            identified("e.jpg", "Toona ciliata_Toon"),
+        ];
+
+        let summary = summarize(items, None);
+
+        assert_eq!((summary.total, summary.succeeded, summary.failed, summary.queued), (5, 3, 1, 1));
+        let tally: Vec<(&str, usize)> = summary.species.iter().map(|tally| (tally.label.as_str(), tally.count)).collect();
+        assert_eq!(tally, vec![("Toona ciliata_Toon", 2), ("Chukrasia tabularis_Chickrasi", 1)]);
+        let paths: Vec<&str> = summary.items.iter().map(|item| item.image_path.as_str()).collect();
+        assert_eq!(paths, vec!["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"]);
+    }
+
+    #[test]
+    fn joins_tallies_to_species_records() {
+        let db = DbConnection::in_memory();
+        db.import_species_file(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/species_data.json")).unwrap();
+
+        let summary = summarize(vec![identified("a.jpg", "Chukrasia tabularis_Chickrasi"), identified("b.jpg", "Not_A_Species")], Some(&db));
+
+        let species = |label: &str| summary.species.iter().find(|tally| tally.label == label).unwrap().species.clone();
+        assert_eq!(species("Chukrasia tabularis_Chickrasi").unwrap().scientific_name, "Chukrasia tabularis");
+        assert!(species("Not_A_Species").is_none());
+    }
+
+    #[test]
+    fn collects_supported_images_sorted() {
+        let directory = std::env::temp_dir().join(format!("treescope-batch-{}", std::process::id()));
+        fs::create_dir_all(directory.join("nested.jpg")).unwrap();
+        for name in ["b.JPG", "a.png", "notes.txt", "c.tiff"] {
+            fs::write(directory.join(name), b"").unwrap();
+        }
+
+        let names: Vec<String> = collect_images(&directory.to_string_lossy()).unwrap().iter()
+            .map(|path| Path::new(path).file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["a.png", "b.JPG", "c.tiff"]);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,8 @@
+use std::process;
+
+fn main() {
+    if let Err(err) = TreeScopeAI::calibrate::run_calibration() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
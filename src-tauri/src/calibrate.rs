@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::path::Path;
+use crate::calibration;
+use crate::classifier::{Classifier, LocalOnnxClassifier, RemoteHttpClassifier};
+use crate::database::DbConnection;
+
+// Command line utility function to fit and store a calibration
+pub fn run_calibration() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() != 3 && args.len() != 5 {
+        println!("Usage: {} <path/to/labelled_images> <path/to/treescope.db> [model.onnx labels.txt]", args[0]);
+        println!("Each subfolder of labelled_images is named after a model label and holds images of it.");
+        println!("treescope.db is the app data database in the app's data directory; it is created if missing.");
+        println!("Without a local model, the remote API configured through API_URL is calibrated.");
+        return Ok(());
+    }
+
+    let images_dir = &args[1];
+    let db_path = &args[2];
+
+    if !Path::new(images_dir).is_dir() {
+        return Err(format!("Image folder not found: {}", images_dir).into());
+    }
+
+    let classifier: Box<dyn Classifier> = match args.get(3).zip(args.get(4)) {
+        Some((model_path, labels_path)) => Box::new(LocalOnnxClassifier::load(Path::new(model_path), Path::new(labels_path))?),
+        None => Box::new(RemoteHttpClassifier::from_env()),
+    };
+
+    let samples = tauri::async_runtime::block_on(calibration::collect_samples(images_dir, classifier.as_ref()))?;
+
+    let correct = samples.iter()
+        .filter(|sample| sample.probabilities.first().is_some_and(|top| top.label == sample.label))
+        .count();
+    let mean_confidence = samples.iter()
+        .filter_map(|sample| sample.probabilities.first())
+        .map(|top| top.probability)
+        .sum::<f64>() / samples.len().max(1) as f64;
+
+    let calibration = calibration::fit(&samples, &classifier.model_version())?;
+
+    println!(
+        "Fitted temperature {:.3} on {} images (accuracy {:.1}%, mean confidence {:.1}%)",
+        calibration.temperature,
+        calibration.samples,
+        100.0 * correct as f64 / samples.len() as f64,
+        100.0 * mean_confidence,
+    );
+    println!("Negative log-likelihood {:.4} -> {:.4}", calibration.nll_before, calibration.nll_after);
+
+    let db = DbConnection::open_app_data(db_path)?;
+    db.save_calibration(&calibration)?;
+    println!("Saved calibration for {}", calibration.model_version);
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use crate::analysis::{self, AnalysisOptions};
+use crate::batch;
+use crate::classifier::{ClassScore, Classifier, Prediction};
+use crate::database::Calibration;
+use crate::open_set::OpenSetSettings;
+use crate::quality::{QualityMode, QualitySettings};
+use crate::tiling::TilingSettings;
+
+/// Fewer labelled images than this give a meaningless fit
+const MIN_SAMPLES: usize = 10;
+
+// Search range for the temperature
+const MIN_TEMPERATURE: f64 = 0.05;
+const MAX_TEMPERATURE: f64 = 20.0;
+
+// Floor for probabilities inside the log-likelihood
+const EPSILON: f64 = 1e-12;
+
+/// A labelled validation image and the uncalibrated distribution the model produced for it
+pub struct Sample {
+    pub label: String,
+    pub probabilities: Vec<ClassScore>,
+}
+
+/// Mean negative log-likelihood of the true labels at a given temperature
+fn negative_log_likelihood(samples: &[Sample], temperature: f64) -> f64 {
+    let total: f64 = samples.iter()
+        .map(|sample| {
+            let (calibrated, residual) = scale(&sample.probabilities, temperature);
+            // A true label missing from the reported classes can only be in the unreported mass
+            let probability = calibrated.iter()
+                .find(|score| score.label == sample.label)
+                .map(|score| score.probability)
+                .unwrap_or(residual);
+            -probability.max(EPSILON).ln()
+        })
+        .sum();
+
+    total / samples.len() as f64
+}
+
+/// Fit the temperature minimizing negative log-likelihood with a golden-section search over log T
+pub fn fit(samples: &[Sample], model_version: &str) -> Result<Calibration, String> {
+    if samples.len() < MIN_SAMPLES {
+        return Err(format!("At least {} labelled images are needed, found {}", MIN_SAMPLES, samples.len()));
+    }
+
+    if !samples.iter().any(|sample| sample.probabilities.iter().any(|score| score.label == sample.label)) {
+        return Err("No folder name matches a label reported by the model".to_string());
+    }
+
+    let nll_at = |log_temperature: f64| negative_log_likelihood(samples, log_temperature.exp());
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = (MIN_TEMPERATURE.ln(), MAX_TEMPERATURE.ln());
+
+    for _ in 0..100 {
+        let left = high - ratio * (high - low);
+        let right = low + ratio * (high - low);
+        if nll_at(left) < nll_at(right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+
+    let temperature = ((low + high) / 2.0).exp();
+
+    Ok(Calibration {
+        model_version: model_version.to_string(),
+        method: "temperature".to_string(),
+        temperature,
+        samples: samples.len() as i64,
+        nll_before: negative_log_likelihood(samples, 1.0),
+        nll_after: negative_log_likelihood(samples, temperature),
+        fitted_at: None,
+    })
+}
+
+/// Classify every image in the label-named subfolders of `directory` without calibration or rejection
+pub async fn collect_samples(directory: &str, classifier: &dyn Classifier) -> Result<Vec<Sample>, String> {
+    let options = AnalysisOptions {
+        top_k: usize::MAX,
+        bypass_cache: true,
+        quality: QualitySettings { mode: QualityMode::Off, ..QualitySettings::default() },
+        open_set: OpenSetSettings { enabled: false, ..OpenSetSettings::default() },
+        // Calibrations only apply to whole-image results
+        tiling: TilingSettings { enabled: false, ..TilingSettings::default() },
+        ..AnalysisOptions::default()
+    };
+
+    let mut label_dirs: Vec<_> = fs::read_dir(directory)
+        .map_err(|e| format!("Failed to read directory {}: {}", directory, e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    label_dirs.sort();
+
+    let mut samples = Vec::new();
+    for label_dir in label_dirs {
+        let label = label_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+        for image_path in batch::collect_images(&label_dir.to_string_lossy())? {
+            // No database: results are neither cached nor calibrated
+            match analysis::analyze(&image_path, classifier, None, &options).await {
+                Ok(result) => samples.push(Sample {
+                    label: label.clone(),
+                    probabilities: result.predictions.into_iter()
+                        .map(|prediction| ClassScore { label: prediction.label, probability: prediction.probability })
+                        .collect(),
+                }),
+                Err(e) => eprintln!("Skipping {}: {}", image_path, e),
+            }
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Temperature-scale a distribution. Probability mass the backend did not report is kept
+/// as an implicit "other" class so a top-1-only response is still scaled sensibly.
+fn scale(probabilities: &[ClassScore], temperature: f64) -> (Vec<ClassScore>, f64) {
+    let exponent = 1.0 / temperature;
+    let residual = (1.0 - probabilities.iter().map(|score| score.probability).sum::<f64>()).max(0.0);
+
+    let scaled: Vec<f64> = probabilities.iter().map(|score| score.probability.max(0.0).powf(exponent)).collect();
+    let scaled_residual = residual.powf(exponent);
+    let total = scaled.iter().sum::<f64>() + scaled_residual;
+
+    if total <= 0.0 {
+        return (probabilities.to_vec(), residual);
+    }
+
+    let calibrated = probabilities.iter()
+        .zip(scaled)
+        .map(|(score, scaled)| ClassScore { label: score.label.clone(), probability: scaled / total })
+        .collect();
+
+    (calibrated, scaled_residual / total)
+}
+
+/// Apply a fitted calibration to a backend prediction
+pub fn apply(prediction: &Prediction, calibration: &Calibration) -> Prediction {
+    let probabilities = match prediction.probabilities.is_empty() {
+        true => vec![ClassScore { label: prediction.label.clone(), probability: prediction.confidence }],
+        false => prediction.probabilities.clone(),
+    };
+
+    let (calibrated, _) = scale(&probabilities, calibration.temperature);
+    Prediction::from_distribution(calibrated).unwrap_or_else(|| prediction.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores(scores: &[(&str, f64)]) -> Vec<ClassScore> {
+        scores.iter().map(|(label, probability)| ClassScore { label: label.to_string(), probability: *probability }).collect()
+    }
+
+    /// The model is 95% sure of its top class but right only 60% of the time
+    fn overconfident_samples() -> Vec<Sample> {
+        (0..20)
+            .map(|index| Sample {
+                label: if index % 5 < 3 { "Toona" } else { "Chukrasia" }.to_string(),
+                probabilities: scores(&[("Toona", 0.95), ("Chukrasia", 0.05)]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn softens_an_overconfident_model() {
+        let calibration = fit(&overconfident_samples(), "local").unwrap();
+        assert!(calibration.temperature > 1.0, "temperature {}", calibration.temperature);
+        assert!(calibration.nll_after <= calibration.nll_before);
+        assert_eq!(calibration.samples, 20);
+
+        // Accuracy is 60%, so the calibrated top probability should land close to it
+        let calibrated = apply(&Prediction::from_distribution(scores(&[("Toona", 0.95), ("Chukrasia", 0.05)])).unwrap(), &calibration);
+        assert!((calibrated.confidence - 0.6).abs() < 0.01, "confidence {}", calibrated.confidence);
+    }
+
+    #[test]
+    fn refuses_too_few_or_unmatched_samples() {
+        assert!(fit(&overconfident_samples()[..MIN_SAMPLES - 1], "local").is_err());
+
+        let unmatched: Vec<Sample> = overconfident_samples().into_iter()
+            .map(|sample| Sample { label: "Tectona".to_string(), ..sample })
+            .collect();
+        assert!(fit(&unmatched, "local").is_err());
+    }
+
+    #[test]
+    fn scales_a_top_1_only_distribution_against_the_unreported_mass() {
+        let (calibrated, residual) = scale(&scores(&[("Toona", 0.9)]), 2.0);
+        // 0.9^0.5 / (0.9^0.5 + 0.1^0.5) = 0.75
+        assert!((calibrated[0].probability - 0.75).abs() < 1e-9);
+        assert!((residual - 0.25).abs() < 1e-9);
+
+        let (unchanged, residual) = scale(&scores(&[("Toona", 0.9)]), 1.0);
+        assert!((unchanged[0].probability - 0.9).abs() < 1e-9);
+        assert!((residual - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn applies_to_predictions_without_a_distribution() {
+        let prediction = Prediction { label: "Toona".to_string(), confidence: 0.9, fallback: false, probabilities: Vec::new() };
+        let calibration = Calibration {
+            model_version: "remote".to_string(),
+            method: "temperature".to_string(),
+            temperature: 2.0,
+            samples: 10,
+            nll_before: 1.0,
+            nll_after: 0.5,
+            fitted_at: None,
+        };
+
+        let calibrated = apply(&prediction, &calibration);
+        assert_eq!(calibrated.label, "Toona");
+        assert!((calibrated.confidence - 0.75).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Too many consecutive failures; requests are rejected until the cooldown passes
+    Open,
+    /// Cooldown has passed; a single trial request decides whether the circuit closes again
+    HalfOpen,
+}
+
+/// Snapshot of the breaker, returned to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    pub retry_after_secs: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+struct BreakerInner {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    last_error: Option<String>,
+    /// A half-open trial was let through and its outcome has not been recorded yet
+    trial_in_flight: bool,
+}
+
+/// Stops calling an endpoint after repeated failures, then lets a single trial through after a cooldown
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerInner {
+                consecutive_failures: 0,
+                opened_at: None,
+                last_error: None,
+                trial_in_flight: false,
+            }),
+        }
+    }
+
+    fn state_of(&self, inner: &BreakerInner) -> CircuitState {
+        match inner.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() >= self.cooldown => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    /// Returns the remaining cooldown if requests are currently blocked. Once the cooldown has passed
+    /// only the first caller gets through as the trial; the others are blocked, with no time left to
+    /// wait, until the trial's outcome is recorded.
+    pub fn check(&self) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        match (self.state_of(&inner), inner.opened_at) {
+            (CircuitState::Open, Some(opened_at)) => Err(self.cooldown.saturating_sub(opened_at.elapsed())),
+            (CircuitState::HalfOpen, _) if inner.trial_in_flight => Err(Duration::ZERO),
+            (CircuitState::HalfOpen, _) => {
+                inner.trial_in_flight = true;
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.trial_in_flight = false;
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.last_error = Some(error.to_string());
+        inner.trial_in_flight = false;
+
+        // A failed trial re-opens the breaker for another full cooldown
+        if inner.consecutive_failures >= self.failure_threshold {
+            if inner.opened_at.is_none() || self.state_of(&inner) == CircuitState::HalfOpen {
+                eprintln!("Circuit breaker opened after {} consecutive failures", inner.consecutive_failures);
+            }
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        let inner = self.inner.lock().unwrap();
+        let state = self.state_of(&inner);
+        let retry_after_secs = match (state, inner.opened_at) {
+            (CircuitState::Open, Some(opened_at)) => Some(self.cooldown.saturating_sub(opened_at.elapsed()).as_secs()),
+            _ => None,
+        };
+
+        CircuitStatus {
+            state,
+            consecutive_failures: inner.consecutive_failures,
+            failure_threshold: self.failure_threshold,
+            retry_after_secs,
+            last_error: inner.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    fn open_breaker() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+        breaker.record_failure("timeout");
+        breaker.record_failure("timeout");
+        breaker
+    }
+
+    #[test]
+    fn opens_after_the_failure_threshold() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        breaker.record_failure("timeout");
+        breaker.record_failure("timeout");
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure("timeout");
+        let status = breaker.status();
+        assert_eq!(status.state, CircuitState::Open);
+        assert_eq!(status.last_error.as_deref(), Some("timeout"));
+        assert!(breaker.check().unwrap_err() > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn half_opens_after_the_cooldown() {
+        let breaker = open_breaker();
+        assert!(breaker.check().is_err());
+
+        thread::sleep(COOLDOWN);
+        assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn lets_a_single_trial_through() {
+        let breaker = open_breaker();
+        thread::sleep(COOLDOWN);
+
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.check(), Err(Duration::ZERO));
+        assert_eq!(breaker.check(), Err(Duration::ZERO));
+
+        breaker.record_success();
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert!(breaker.check().is_ok() && breaker.check().is_ok());
+    }
+
+    #[test]
+    fn failed_trial_reopens_for_another_cooldown() {
+        let breaker = open_breaker();
+        thread::sleep(COOLDOWN);
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure("connection refused");
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        assert!(breaker.check().unwrap_err() > COOLDOWN / 2);
+
+        thread::sleep(COOLDOWN);
+        assert!(breaker.check().is_ok());
+    }
+}
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use super::{AnalysisError, ClassScore, Classifier, ImageInput, Prediction};
+
+/// Returns a fixed mock prediction for demonstrations without a model or network.
+/// Only registered when demo mode is explicitly enabled.
+pub struct DemoClassifier;
+
+impl DemoClassifier {
+    /// Demo mode is opt-in through `TREESCOPE_DEMO_MODE=1`
+    pub fn enabled() -> bool {
+        matches!(
+            std::env::var("TREESCOPE_DEMO_MODE").as_deref(),
+            Ok("1") | Ok("true")
+        )
+    }
+}
+
+#[async_trait]
+impl Classifier for DemoClassifier {
+    fn name(&self) -> &str {
+        "demo"
+    }
+
+    async fn classify(&self, _image: &ImageInput) -> Result<Prediction, AnalysisError> {
+        Ok(Prediction {
+            label: "Oak_Tree".to_string(),
+            confidence: 0.85,
+            fallback: true,
+            probabilities: vec![ClassScore { label: "Oak_Tree".to_string(), probability: 0.85 }],
+        })
+    }
+}
@@ -0,0 +1,103 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+/// Reasons an image analysis can fail, serialized to the frontend as `{ kind, message, status }`
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisError {
+    /// The classifier endpoint could not be reached
+    Network(String),
+    /// The classifier endpoint did not answer in time
+    Timeout(String),
+    /// Requests are paused after repeated endpoint failures
+    CircuitOpen { retry_after_secs: u64 },
+    /// The classifier endpoint answered with a non-2xx status
+    HttpStatus { status: u16, body: String },
+    /// The classifier answered with something that is not a usable prediction
+    BadPayload(String),
+    /// The file extension is not one of the supported image formats
+    UnsupportedFormat(String),
+    /// The image file does not exist
+    FileMissing(String),
+    /// The image file exists but could not be read
+    Io(String),
+    /// No backend is configured, or the backend failed internally
+    Backend(String),
+    /// The image failed the quality gate while it is set to reject
+    QualityRejected(Vec<String>),
+    /// The classifier was unreachable, so the image was queued for later analysis
+    Queued { queue_id: i64 },
+}
+
+impl AnalysisError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AnalysisError::Network(_) => "network",
+            AnalysisError::Timeout(_) => "timeout",
+            AnalysisError::CircuitOpen { .. } => "circuit_open",
+            AnalysisError::HttpStatus { .. } => "http_status",
+            AnalysisError::BadPayload(_) => "bad_payload",
+            AnalysisError::UnsupportedFormat(_) => "unsupported_format",
+            AnalysisError::FileMissing(_) => "file_missing",
+            AnalysisError::Io(_) => "io",
+            AnalysisError::Backend(_) => "backend",
+            AnalysisError::QualityRejected(_) => "quality_rejected",
+            AnalysisError::Queued { .. } => "queued",
+        }
+    }
+
+    /// Whether the failure says the endpoint is unreachable or unhealthy, as opposed to a bad request
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            AnalysisError::Network(_) | AnalysisError::Timeout(_) | AnalysisError::CircuitOpen { .. } => true,
+            AnalysisError::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::Network(e) => write!(f, "API request failed: {}", e),
+            AnalysisError::Timeout(e) => write!(f, "API request timed out: {}", e),
+            AnalysisError::CircuitOpen { retry_after_secs } => write!(f, "Classifier API is unavailable, try again in {}s", retry_after_secs),
+            AnalysisError::HttpStatus { status, body } => write!(f, "API returned status {}: {}", status, body),
+            AnalysisError::BadPayload(e) => write!(f, "Invalid API response: {}", e),
+            AnalysisError::UnsupportedFormat(ext) => write!(f, "Unsupported image format: {}", ext),
+            AnalysisError::FileMissing(path) => write!(f, "File not found: {}", path),
+            AnalysisError::Io(e) => write!(f, "Failed to read file: {}", e),
+            AnalysisError::Backend(e) => write!(f, "{}", e),
+            AnalysisError::QualityRejected(issues) => write!(f, "Image rejected by quality check: {}", issues.join("; ")),
+            AnalysisError::Queued { queue_id } => write!(f, "Classifier is unreachable; image queued for analysis (#{})", queue_id),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+impl From<reqwest::Error> for AnalysisError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AnalysisError::Timeout(e.to_string())
+        } else if e.is_decode() {
+            AnalysisError::BadPayload(e.to_string())
+        } else {
+            AnalysisError::Network(e.to_string())
+        }
+    }
+}
+
+impl Serialize for AnalysisError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let status = match self {
+            AnalysisError::HttpStatus { status, .. } => Some(*status),
+            _ => None,
+        };
+
+        let mut state = serializer.serialize_struct("AnalysisError", 3)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("status", &status)?;
+        state.end()
+    }
+}
@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+use super::{AnalysisError, ClassScore, Classifier, ImageInput, Prediction};
+
+// Fallback input resolution when the exported model leaves it symbolic
+const DEFAULT_INPUT_SIZE: usize = 224;
+
+type OnnxModel = TypedRunnableModel<TypedModel>;
+
+/// Classifier that runs an exported ONNX model on the CPU, so identification works without connectivity
+pub struct LocalOnnxClassifier {
+    model: Arc<OnnxModel>,
+    labels: Arc<Vec<String>>,
+    input_size: (usize, usize),
+    model_hash: String,
+}
+
+impl LocalOnnxClassifier {
+    /// Load a model and its class names (one per line, in model output order)
+    pub fn load(model_path: &Path, labels_path: &Path) -> Result<Self, String> {
+        let labels: Vec<String> = fs::read_to_string(labels_path)
+            .map_err(|e| format!("Failed to read class labels: {}", e))?
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect();
+
+        if labels.is_empty() {
+            return Err(format!("No class labels found in {}", labels_path.display()));
+        }
+
+        let model_bytes = fs::read(model_path)
+            .map_err(|e| format!("Failed to read ONNX model: {}", e))?;
+        let model_hash = format!("{:x}", Sha256::digest(&model_bytes));
+
+        let model = tract_onnx::onnx()
+            .model_for_read(&mut model_bytes.as_slice())
+            .map_err(|e| format!("Failed to load ONNX model: {}", e))?;
+
+        // Models are exported as NCHW; take the spatial size from the graph when it is fixed
+        let input_size = {
+            let fact = model.input_fact(0).map_err(|e| format!("Invalid ONNX model input: {}", e))?;
+            let dim = |i: usize| fact.shape.dim(i)
+                .and_then(|d| d.concretize())
+                .and_then(|d| d.as_i64())
+                .map(|d| d as usize)
+                .unwrap_or(DEFAULT_INPUT_SIZE);
+            (dim(2), dim(3))
+        };
+
+        let model = model
+            .with_input_fact(0, f32::fact([1, 3, input_size.0, input_size.1]).into())
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| format!("Failed to prepare ONNX model: {}", e))?;
+
+        eprintln!("Loaded ONNX model with {} classes, input {}x{}", labels.len(), input_size.0, input_size.1);
+
+        Ok(LocalOnnxClassifier {
+            model: Arc::new(model),
+            labels: Arc::new(labels),
+            input_size,
+            model_hash,
+        })
+    }
+}
+
+#[async_trait]
+impl Classifier for LocalOnnxClassifier {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn model_version(&self) -> String {
+        format!("onnx:{}", &self.model_hash[..16])
+    }
+
+    fn input_size(&self) -> Option<u32> {
+        Some(self.input_size.0.max(self.input_size.1) as u32)
+    }
+
+    async fn classify(&self, image: &ImageInput) -> Result<Prediction, AnalysisError> {
+        let model = self.model.clone();
+        let labels = self.labels.clone();
+        let input_size = self.input_size;
+        let data = image.data.clone();
+
+        // Inference is CPU bound, so keep it off the async executor
+        tauri::async_runtime::spawn_blocking(move || run_inference(&model, &labels, input_size, &data))
+            .await
+            .map_err(|e| AnalysisError::Backend(format!("Inference task failed: {}", e)))?
+    }
+
+    async fn class_labels(&self) -> Result<Vec<String>, AnalysisError> {
+        Ok(self.labels.to_vec())
+    }
+}
+
+fn run_inference(model: &OnnxModel, labels: &[String], input_size: (usize, usize), data: &[u8]) -> Result<Prediction, AnalysisError> {
+    let (height, width) = input_size;
+
+    let image = image::load_from_memory(data)
+        .map_err(|e| AnalysisError::UnsupportedFormat(format!("failed to decode image: {}", e)))?
+        .resize_exact(width as u32, height as u32, image::imageops::FilterType::Triangle)
+        .to_rgb8();
+
+    // RGB scaled to [0, 1], matching the training pipeline
+    let input: Tensor = tract_ndarray::Array4::from_shape_fn((1, 3, height, width), |(_, c, y, x)| {
+        image.get_pixel(x as u32, y as u32)[c] as f32 / 255.0
+    }).into();
+
+    let outputs = model.run(tvec!(input.into()))
+        .map_err(|e| AnalysisError::Backend(format!("Inference failed: {}", e)))?;
+
+    let scores: Vec<f32> = outputs[0]
+        .to_array_view::<f32>()
+        .map_err(|e| AnalysisError::Backend(format!("Unexpected model output: {}", e)))?
+        .iter()
+        .copied()
+        .collect();
+
+    if scores.len() != labels.len() {
+        return Err(AnalysisError::Backend(format!("Model produced {} scores for {} class labels", scores.len(), labels.len())));
+    }
+
+    let probabilities = to_probabilities(&scores)
+        .into_iter()
+        .zip(labels.iter())
+        .map(|(probability, label)| ClassScore {
+            label: label.clone(),
+            probability: probability as f64,
+        })
+        .collect();
+
+    Prediction::from_distribution(probabilities)
+        .ok_or_else(|| AnalysisError::Backend("Model produced no scores".to_string()))
+}
+
+/// Apply softmax unless the model already exported normalized probabilities
+fn to_probabilities(scores: &[f32]) -> Vec<f32> {
+    let sum: f32 = scores.iter().sum();
+    if scores.iter().all(|s| (0.0..=1.0).contains(s)) && (sum - 1.0).abs() < 1e-3 {
+        return scores.to_vec();
+    }
+
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = scores.iter().map(|s| (s - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    exps.iter().map(|e| e / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn keeps_normalized_probabilities() {
+        assert_close(&to_probabilities(&[0.7, 0.2, 0.1]), &[0.7, 0.2, 0.1]);
+    }
+
+    #[test]
+    fn applies_softmax_to_logits() {
+        let probabilities = to_probabilities(&[2.0, 1.0, 0.0]);
+        assert_close(&probabilities, &[0.665241, 0.244728, 0.090031]);
+
+        // Scores in 0..1 that do not sum to one are still logits
+        assert_close(&to_probabilities(&[0.5, 0.5, 0.5]), &[1.0 / 3.0; 3]);
+    }
+
+    #[test]
+    fn softmax_is_stable_for_large_logits() {
+        let probabilities = to_probabilities(&[1000.0, 1000.0]);
+        assert_close(&probabilities, &[0.5, 0.5]);
+    }
+}
@@ -0,0 +1,297 @@
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+pub mod circuit;
+pub mod demo;
+pub mod error;
+pub mod local;
+pub mod remote;
+
+pub use circuit::CircuitStatus;
+pub use demo::DemoClassifier;
+pub use error::AnalysisError;
+pub use local::LocalOnnxClassifier;
+pub use remote::RemoteHttpClassifier;
+
+/// File extensions accepted for analysis
+pub const SUPPORTED_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "bmp", "tiff", "webp"];
+
+/// Image file handed to a classifier backend
+#[derive(Clone)]
+pub struct ImageInput {
+    pub file_name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl ImageInput {
+    /// Load an image from disk, checking that it exists and has a supported extension
+    pub fn from_path(file_path: &str) -> Result<Self, AnalysisError> {
+        // Create a longer-lived string before creating the Path
+        let normalized_path = if cfg!(target_os = "windows") && !file_path.starts_with("\\\\?\\") {
+            // On Windows, ensure the path is properly formatted with long path support
+            format!("\\\\?\\{}", file_path.replace("/", "\\"))
+        } else {
+            file_path.to_string()
+        };
+
+        let path = Path::new(&normalized_path);
+
+        if !path.exists() {
+            return Err(AnalysisError::FileMissing(file_path.to_string()));
+        }
+
+        // Get file extension to verify it's an image
+        let extension = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => ext.to_lowercase(),
+            None => return Err(AnalysisError::UnsupportedFormat("file has no extension".to_string())),
+        };
+
+        // Get MIME type based on extension, rejecting anything that is not a supported image format
+        let mime_type = match extension.as_str() {
+            "jpg" | "jpeg" => "image/jpeg",
+            "png" => "image/png",
+            "bmp" => "image/bmp",
+            "tiff" => "image/tiff",
+            "webp" => "image/webp",
+            _ => return Err(AnalysisError::UnsupportedFormat(extension)),
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| AnalysisError::Io(e.to_string()))?;
+
+        Ok(ImageInput {
+            file_name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            mime_type: mime_type.to_string(),
+            data,
+        })
+    }
+
+    /// SHA-256 of the image bytes, as lowercase hex
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.data);
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Probability a backend assigned to a single class
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassScore {
+    pub label: String,
+    pub probability: f64,
+}
+
+/// Prediction returned by a classifier backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prediction {
+    pub label: String,
+    pub confidence: f64,
+    #[serde(rename = "_fallback", default)]
+    pub fallback: bool,
+    /// Every class score the backend reported, highest probability first
+    #[serde(default)]
+    pub probabilities: Vec<ClassScore>,
+}
+
+impl Prediction {
+    /// Build a prediction from a class distribution, taking the most probable class as the label
+    pub fn from_distribution(mut probabilities: Vec<ClassScore>) -> Option<Self> {
+        probabilities.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        let top = probabilities.first()?.clone();
+
+        Some(Prediction {
+            label: top.label,
+            confidence: top.probability,
+            fallback: false,
+            probabilities,
+        })
+    }
+}
+
+/// A wood species classifier that `analyze_local_image` can delegate to
+#[async_trait]
+pub trait Classifier: Send + Sync {
+    /// Identifier used to select this backend
+    fn name(&self) -> &str;
+
+    /// Identifies the model behind the backend; cached results are only reused for the same version
+    fn model_version(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// Longest image side the model works at; larger images are downsized before classification
+    fn input_size(&self) -> Option<u32> {
+        None
+    }
+
+    /// Whether the backend decodes images itself, so files the app cannot decode are sent as they are
+    fn accepts_undecoded_images(&self) -> bool {
+        false
+    }
+
+    /// Classify a single image
+    async fn classify(&self, image: &ImageInput) -> Result<Prediction, AnalysisError>;
+
+    /// Prepare the backend ahead of the first request, e.g. wake a cold-starting server
+    async fn warm_up(&self) -> Result<(), AnalysisError> {
+        Ok(())
+    }
+
+    /// Class names the model can predict, used to check them against `model_labels`
+    async fn class_labels(&self) -> Result<Vec<String>, AnalysisError> {
+        Err(AnalysisError::Backend(format!("The {} backend does not list its classes", self.name())))
+    }
+
+    /// Circuit breaker state for backends that guard a remote endpoint
+    fn circuit_status(&self) -> Option<CircuitStatus> {
+        None
+    }
+}
+
+/// Set of available classifier backends and the one currently in use
+#[derive(Default)]
+pub struct ClassifierRegistry {
+    backends: HashMap<String, Arc<dyn Classifier>>,
+    active: Option<String>,
+}
+
+impl ClassifierRegistry {
+    /// Register a backend; the first one registered becomes the active backend
+    pub fn register(&mut self, backend: Arc<dyn Classifier>) {
+        let name = backend.name().to_string();
+        if self.active.is_none() {
+            self.active = Some(name.clone());
+        }
+        self.backends.insert(name, backend);
+    }
+
+    pub fn set_active(&mut self, name: &str) -> Result<(), String> {
+        if !self.backends.contains_key(name) {
+            return Err(format!("Unknown classifier backend: {}", name));
+        }
+        self.active = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Classifier>> {
+        self.backends.get(name).cloned()
+    }
+
+    pub fn active(&self) -> Option<Arc<dyn Classifier>> {
+        self.active.as_ref().and_then(|name| self.backends.get(name).cloned())
+    }
+
+    pub fn active_name(&self) -> Option<String> {
+        self.active.clone()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.backends.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// Backend with a fixed class list that ranks its classes in order, for tests
+#[cfg(test)]
+pub(crate) struct StubClassifier {
+    pub name: &'static str,
+    pub classes: Vec<String>,
+    pub accepts_undecoded: bool,
+    /// Images passed to `classify`, in order
+    pub received: std::sync::Mutex<Vec<ImageInput>>,
+}
+
+#[cfg(test)]
+impl StubClassifier {
+    pub fn new(name: &'static str, classes: &[&str]) -> Self {
+        StubClassifier {
+            name,
+            classes: classes.iter().map(|class| class.to_string()).collect(),
+            accepts_undecoded: false,
+            received: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Classifier for StubClassifier {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn accepts_undecoded_images(&self) -> bool {
+        self.accepts_undecoded
+    }
+
+    /// The first class gets the highest probability, each later one less
+    async fn classify(&self, image: &ImageInput) -> Result<Prediction, AnalysisError> {
+        self.received.lock().unwrap().push(image.clone());
+        let total: usize = (1..=self.classes.len()).sum();
+        let scores = self.classes.iter().enumerate()
+            .map(|(index, label)| ClassScore { label: label.clone(), probability: (self.classes.len() - index) as f64 / total as f64 })
+            .collect();
+        Prediction::from_distribution(scores).ok_or_else(|| AnalysisError::Backend("No classes".to_string()))
+    }
+
+    async fn class_labels(&self) -> Result<Vec<String>, AnalysisError> {
+        Ok(self.classes.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("treescope-input-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_supported_images_by_extension() {
+        let path = temp_file("sample.JPG", b"jpeg bytes");
+        let image = ImageInput::from_path(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.mime_type, "image/jpeg");
+        assert_eq!(image.file_name, path.file_name().unwrap().to_string_lossy());
+        assert_eq!(image.data, b"jpeg bytes");
+    }
+
+    #[test]
+    fn rejects_missing_and_unsupported_files() {
+        let missing = std::env::temp_dir().join("treescope-input-missing.png");
+        let missing = missing.to_string_lossy().to_string();
+        assert_eq!(ImageInput::from_path(&missing).err(), Some(AnalysisError::FileMissing(missing)));
+
+        let path = temp_file("notes.txt", b"text");
+        let result = ImageInput::from_path(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.err(), Some(AnalysisError::UnsupportedFormat("txt".to_string())));
+    }
+
+    #[test]
+    fn content_hash_is_sha256_of_the_bytes() {
+        let image = |data: &[u8]| ImageInput {
+            file_name: "a.png".to_string(),
+            mime_type: "image/png".to_string(),
+            data: data.to_vec(),
+        };
+
+        assert_eq!(image(b"abc").content_hash(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // Only the bytes matter, not the file name
+        let mut renamed = image(b"abc");
+        renamed.file_name = "b.png".to_string();
+        assert_eq!(renamed.content_hash(), image(b"abc").content_hash());
+        assert_ne!(image(b"abd").content_hash(), image(b"abc").content_hash());
+    }
+}
@@ -0,0 +1,263 @@
+use async_trait::async_trait;
+use std::time::Duration;
+use super::circuit::{CircuitBreaker, CircuitStatus};
+use super::{AnalysisError, ClassScore, Classifier, ImageInput, Prediction};
+
+const DEFAULT_API_URL: &str = "https://shakirul-sust-treescopy-api.hf.space/predict";
+
+// Upper bound on a single backoff delay
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Timeout, retry and circuit breaker settings for the remote classifier
+#[derive(Debug, Clone)]
+pub struct RemoteConfig {
+    pub api_url: String,
+    /// Version of the model deployed behind the endpoint, used to key cached results
+    pub model_version: Option<String>,
+    /// Longest side images are downsized to before upload
+    pub input_size: Option<u32>,
+    pub timeout: Duration,
+    /// Retries after the first attempt for transient failures
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on each subsequent one
+    pub initial_backoff: Duration,
+    /// Consecutive failed requests before the circuit opens
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a trial request is allowed
+    pub cooldown: Duration,
+    /// Timeout for the startup warm-up ping, which has to cover a cold start
+    pub warm_up_timeout: Duration,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        RemoteConfig {
+            api_url: DEFAULT_API_URL.to_string(),
+            model_version: None,
+            input_size: Some(640),
+            timeout: Duration::from_secs(30),
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(2),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(60),
+            warm_up_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RemoteConfig {
+    /// Read overrides from `API_URL`, `API_MODEL_VERSION`, `API_INPUT_SIZE`, `API_TIMEOUT_SECS`,
+    /// `API_MAX_RETRIES`, `API_BACKOFF_MS`, `API_FAILURE_THRESHOLD` and `API_COOLDOWN_SECS`
+    pub fn from_env() -> Self {
+        fn env_u64(name: &str) -> Option<u64> {
+            std::env::var(name).ok().and_then(|value| value.parse().ok())
+        }
+
+        let defaults = RemoteConfig::default();
+        RemoteConfig {
+            api_url: std::env::var("API_URL").unwrap_or(defaults.api_url),
+            model_version: std::env::var("API_MODEL_VERSION").ok(),
+            input_size: env_u64("API_INPUT_SIZE").map(|n| n as u32).or(defaults.input_size),
+            timeout: env_u64("API_TIMEOUT_SECS").map(Duration::from_secs).unwrap_or(defaults.timeout),
+            max_retries: env_u64("API_MAX_RETRIES").map(|n| n as u32).unwrap_or(defaults.max_retries),
+            initial_backoff: env_u64("API_BACKOFF_MS").map(Duration::from_millis).unwrap_or(defaults.initial_backoff),
+            failure_threshold: env_u64("API_FAILURE_THRESHOLD").map(|n| n as u32).unwrap_or(defaults.failure_threshold),
+            cooldown: env_u64("API_COOLDOWN_SECS").map(Duration::from_secs).unwrap_or(defaults.cooldown),
+            warm_up_timeout: defaults.warm_up_timeout,
+        }
+    }
+
+    /// The service root, which serves the `/` health route
+    pub fn base_url(&self) -> &str {
+        self.api_url.trim_end_matches('/').trim_end_matches("/predict")
+    }
+}
+
+/// Classifier backed by the hosted `/predict` HTTP endpoint
+pub struct RemoteHttpClassifier {
+    config: RemoteConfig,
+    client: reqwest::Client,
+    breaker: CircuitBreaker,
+}
+
+impl RemoteHttpClassifier {
+    pub fn new(config: RemoteConfig) -> Self {
+        RemoteHttpClassifier {
+            breaker: CircuitBreaker::new(config.failure_threshold, config.cooldown),
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    /// Build from environment overrides, falling back to the hosted Space
+    pub fn from_env() -> Self {
+        Self::new(RemoteConfig::from_env())
+    }
+
+    async fn send_prediction(&self, image: &ImageInput) -> Result<Prediction, AnalysisError> {
+        // Create multipart form with correct boundary
+        let file_part = reqwest::multipart::Part::bytes(image.data.clone())
+            .file_name(image.file_name.clone())
+            .mime_str(&image.mime_type)
+            .map_err(|_| AnalysisError::UnsupportedFormat(image.mime_type.clone()))?;
+
+        let form = reqwest::multipart::Form::new().part("file", file_part);
+
+        // Send the request to the API
+        let response = self.client.post(&self.config.api_url)
+            .multipart(form)
+            .header("Accept", "application/json")
+            .timeout(self.config.timeout)
+            .send()
+            .await?;
+
+        // Check response status
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AnalysisError::HttpStatus { status: status.as_u16(), body });
+        }
+
+        // Parse response JSON
+        let api_result: serde_json::Value = response.json().await?;
+
+        parse_prediction(&api_result)
+    }
+}
+
+#[async_trait]
+impl Classifier for RemoteHttpClassifier {
+    fn name(&self) -> &str {
+        "remote"
+    }
+
+    fn model_version(&self) -> String {
+        // Without an explicit version, results are tied to the endpoint they came from
+        match &self.config.model_version {
+            Some(version) => format!("remote:{}", version),
+            None => format!("remote:{}", self.config.api_url),
+        }
+    }
+
+    fn input_size(&self) -> Option<u32> {
+        self.config.input_size
+    }
+
+    /// The server has its own decoders, which handle some files the image crate rejects
+    fn accepts_undecoded_images(&self) -> bool {
+        true
+    }
+
+    async fn classify(&self, image: &ImageInput) -> Result<Prediction, AnalysisError> {
+        if let Err(remaining) = self.breaker.check() {
+            return Err(AnalysisError::CircuitOpen { retry_after_secs: remaining.as_secs() });
+        }
+
+        let mut backoff = self.config.initial_backoff;
+        let mut attempt = 0;
+
+        loop {
+            match self.send_prediction(image).await {
+                Ok(prediction) => {
+                    self.breaker.record_success();
+                    return Ok(prediction);
+                },
+                // The Space may still be cold-starting or briefly overloaded
+                Err(e) if e.is_unreachable() && attempt < self.config.max_retries => {
+                    attempt += 1;
+                    eprintln!("Prediction attempt {} failed ({}), retrying in {:?}", attempt, e, backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                },
+                Err(e) => {
+                    // Only endpoint health counts against the breaker; an endpoint that answered a
+                    // bad request is up, which also ends a half-open trial
+                    match e.is_unreachable() {
+                        true => self.breaker.record_failure(&e.to_string()),
+                        false => self.breaker.record_success(),
+                    }
+                    return Err(e);
+                },
+            }
+        }
+    }
+
+    async fn warm_up(&self) -> Result<(), AnalysisError> {
+        let url = format!("{}/", self.config.base_url());
+
+        let response = self.client.get(&url)
+            .timeout(self.config.warm_up_timeout)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(AnalysisError::HttpStatus { status: status.as_u16(), body: response.text().await.unwrap_or_default() });
+        }
+
+        self.breaker.record_success();
+        Ok(())
+    }
+
+    async fn class_labels(&self) -> Result<Vec<String>, AnalysisError> {
+        let url = format!("{}/species", self.config.base_url());
+
+        let response = self.client.get(&url)
+            .timeout(self.config.warm_up_timeout)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(AnalysisError::HttpStatus { status: status.as_u16(), body: response.text().await.unwrap_or_default() });
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        body["species"].as_array()
+            .ok_or_else(|| AnalysisError::BadPayload("missing `species`".to_string()))?
+            .iter()
+            .map(|label| label.as_str()
+                .map(|label| label.to_string())
+                .ok_or_else(|| AnalysisError::BadPayload("non-string entry in `species`".to_string())))
+            .collect()
+    }
+
+    fn circuit_status(&self) -> Option<CircuitStatus> {
+        Some(self.breaker.status())
+    }
+}
+
+/// Convert a `/predict` response body into a prediction, rejecting anything incomplete
+fn parse_prediction(api_result: &serde_json::Value) -> Result<Prediction, AnalysisError> {
+    if let Some(error) = api_result["error"].as_str() {
+        return Err(AnalysisError::BadPayload(error.to_string()));
+    }
+
+    let label = api_result["label"].as_str()
+        .ok_or_else(|| AnalysisError::BadPayload("missing `label`".to_string()))?
+        .to_string();
+    let confidence = api_result["confidence"].as_f64()
+        .ok_or_else(|| AnalysisError::BadPayload("missing `confidence`".to_string()))?;
+
+    // Newer API versions return the full class distribution alongside the top label
+    let mut probabilities: Vec<ClassScore> = api_result["probabilities"].as_object()
+        .map(|scores| scores.iter()
+            .filter_map(|(label, probability)| Some(ClassScore {
+                label: label.clone(),
+                probability: probability.as_f64()?,
+            }))
+            .collect())
+        .unwrap_or_default();
+
+    if probabilities.is_empty() {
+        probabilities.push(ClassScore { label: label.clone(), probability: confidence });
+    }
+    probabilities.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+
+    Ok(Prediction {
+        label,
+        confidence,
+        fallback: false,
+        probabilities,
+    })
+}
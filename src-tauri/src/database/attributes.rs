@@ -0,0 +1,345 @@
+use rusqlite::{Connection, Result, Row, params};
+use serde::Serialize;
+use super::DbConnection;
+use super::species::SpeciesProperties;
+
+// Queryable copies of the wood anatomy and tree traits kept as JSON in `species.properties`
+pub(super) const CREATE_ATTRIBUTE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS wood_anatomy (
+        species_id INTEGER PRIMARY KEY,
+        density_g_cm3 REAL,
+        density_min REAL,
+        density_max REAL,
+        xylem_porosity TEXT,
+        growth_ring_distinct BOOLEAN,
+        FOREIGN KEY (species_id) REFERENCES species(id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS idx_wood_anatomy_density ON wood_anatomy (density_min, density_max);
+    CREATE INDEX IF NOT EXISTS idx_wood_anatomy_porosity ON wood_anatomy (xylem_porosity);
+
+    CREATE TABLE IF NOT EXISTS tree_traits (
+        species_id INTEGER PRIMARY KEY,
+        height_m REAL,
+        height_min_m REAL,
+        height_max_m REAL,
+        bark_thickness_mm REAL,
+        bark_min_mm REAL,
+        bark_max_mm REAL,
+        deciduous BOOLEAN,
+        shade_tolerant BOOLEAN,
+        FOREIGN KEY (species_id) REFERENCES species(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS species_phenology (
+        species_id INTEGER NOT NULL,
+        event TEXT NOT NULL CHECK (event IN ('flowering', 'fruiting')),
+        month INTEGER NOT NULL CHECK (month BETWEEN 1 AND 12),
+        PRIMARY KEY (species_id, event, month),
+        FOREIGN KEY (species_id) REFERENCES species(id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS idx_species_phenology_month ON species_phenology (event, month);
+";
+
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june",
+    "july", "august", "september", "october", "november", "december",
+];
+
+// Periods covering every month
+const YEAR_ROUND: [&str; 4] = ["year-round", "year round", "all year", "throughout the year"];
+
+/// Queryable attributes of one species. Ranges fall back to the single value when no range was recorded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SpeciesAttributes {
+    pub species_id: i64,
+    pub density_g_cm3: Option<f64>,
+    pub density_min: Option<f64>,
+    pub density_max: Option<f64>,
+    pub xylem_porosity: Option<String>,
+    pub growth_ring_distinct: Option<bool>,
+    pub height_m: Option<f64>,
+    pub height_min_m: Option<f64>,
+    pub height_max_m: Option<f64>,
+    pub bark_thickness_mm: Option<f64>,
+    pub bark_min_mm: Option<f64>,
+    pub bark_max_mm: Option<f64>,
+    pub deciduous: Option<bool>,
+    pub shade_tolerant: Option<bool>,
+    /// Months 1-12
+    pub flowering_months: Vec<u32>,
+    pub fruiting_months: Vec<u32>,
+}
+
+/// En and em dashes, including en dashes that were stored as mis-decoded UTF-8, become plain hyphens
+fn normalize_dashes(text: &str) -> String {
+    text.replace("\u{e2}\u{20ac}\u{201c}", "-")
+        .replace("\u{e2}\u{20ac}\u{201d}", "-")
+        .replace(['\u{2013}', '\u{2014}'], "-")
+}
+
+/// Parse `"0.60-0.70"` into its bounds; a lone number is both bounds
+fn parse_range(range: &str) -> Option<(f64, f64)> {
+    let range = normalize_dashes(range);
+    let mut bounds = range.split('-').map(|bound| bound.trim().parse::<f64>());
+    match (bounds.next(), bounds.next(), bounds.next()) {
+        (Some(Ok(min)), Some(Ok(max)), None) => Some((min.min(max), min.max(max))),
+        (Some(Ok(value)), None, None) => Some((value, value)),
+        _ => None,
+    }
+}
+
+fn range_or_value(range: Option<&str>, value: Option<f64>) -> (Option<f64>, Option<f64>) {
+    match range.and_then(parse_range).or(value.map(|value| (value, value))) {
+        Some((min, max)) => (Some(min), Some(max)),
+        None => (None, None),
+    }
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let name = name.trim().to_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    MONTHS.iter().position(|month| month.starts_with(&name)).map(|index| index as u32 + 1)
+}
+
+/// Expand `"April-June"` or `"November-February"` into month numbers. Comma-separated spans are
+/// combined, trailing notes such as `"(nearly continuous)"` are ignored and `"Year-round"` is every month.
+fn parse_months(period: &str) -> Vec<u32> {
+    let period = normalize_dashes(period).to_lowercase();
+    if YEAR_ROUND.iter().any(|phrase| period.contains(phrase)) {
+        return (1..=12).collect();
+    }
+    let period = period.split('(').next().unwrap_or("");
+    let mut months = Vec::new();
+
+    for span in period.split(',') {
+        let ends: Vec<Option<u32>> = span.split('-').map(month_number).collect();
+        let (start, end) = match ends.as_slice() {
+            [Some(month)] => (*month, *month),
+            [Some(start), Some(end)] => (*start, *end),
+            _ => continue,
+        };
+
+        let mut month = start;
+        loop {
+            if !months.contains(&month) {
+                months.push(month);
+            }
+            if month == end {
+                break;
+            }
+            month = month % 12 + 1;
+        }
+    }
+
+    months.sort_unstable();
+    months
+}
+
+/// `"semi-ring porous"` and `"Semi-ring-porous"` are stored alike, matching the `XylemPorosity` names
+fn normalize_porosity(porosity: &str) -> String {
+    porosity.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase()
+}
+
+fn growth_ring_distinct(growth_ring: &str) -> Option<bool> {
+    match growth_ring.trim().to_lowercase().as_str() {
+        "distinct" => Some(true),
+        "indistinct" => Some(false),
+        _ => None,
+    }
+}
+
+/// `shade_tolerant` and `shade_intolerant` are separate flags in the source data
+fn shade_tolerance(properties: &SpeciesProperties) -> Option<bool> {
+    match (properties.traits.shade_tolerant, properties.traits.shade_intolerant) {
+        (Some(true), _) => Some(true),
+        (_, Some(true)) => Some(false),
+        (Some(false), _) => Some(false),
+        _ => None,
+    }
+}
+
+/// Replace the attribute rows of one species with values derived from its properties
+pub(super) fn write_attributes(conn: &Connection, species_id: i64, properties: &SpeciesProperties) -> Result<()> {
+    let wood = &properties.wood_anatomy;
+    let traits = &properties.traits;
+
+    let (density_min, density_max) = range_or_value(wood.density_range.as_deref(), wood.density_g_cm3);
+    conn.execute(
+        "INSERT OR REPLACE INTO wood_anatomy
+         (species_id, density_g_cm3, density_min, density_max, xylem_porosity, growth_ring_distinct)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            species_id,
+            wood.density_g_cm3,
+            density_min,
+            density_max,
+            wood.xylem_porosity.as_deref().map(normalize_porosity),
+            wood.growth_ring.as_deref().and_then(growth_ring_distinct),
+        ],
+    )?;
+
+    let (height_min, height_max) = range_or_value(traits.tree_height_range.as_deref(), traits.tree_height_m);
+    let (bark_min, bark_max) = range_or_value(traits.bark_thickness_range.as_deref(), traits.bark_thickness_mm);
+    conn.execute(
+        "INSERT OR REPLACE INTO tree_traits
+         (species_id, height_m, height_min_m, height_max_m, bark_thickness_mm, bark_min_mm, bark_max_mm, deciduous, shade_tolerant)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            species_id,
+            traits.tree_height_m,
+            height_min,
+            height_max,
+            traits.bark_thickness_mm,
+            bark_min,
+            bark_max,
+            traits.deciduous,
+            shade_tolerance(properties),
+        ],
+    )?;
+
+    conn.execute("DELETE FROM species_phenology WHERE species_id = ?", params![species_id])?;
+    for (event, period) in [("flowering", &traits.flowering_time), ("fruiting", &traits.fruiting_time)] {
+        for month in period.as_deref().map(parse_months).unwrap_or_default() {
+            conn.execute(
+                "INSERT INTO species_phenology (species_id, event, month) VALUES (?, ?, ?)",
+                params![species_id, event, month],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fill the attribute tables from the `properties` JSON of every species
+pub(super) fn backfill(conn: &Connection) -> Result<()> {
+    let rows: Vec<(i64, Option<String>)> = {
+        let mut stmt = conn.prepare("SELECT id, properties FROM species")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_>>()?
+    };
+
+    for (species_id, properties) in rows {
+        // Unreadable properties leave the species without attributes rather than failing the migration
+        let properties: SpeciesProperties = match properties {
+            Some(properties) => serde_json::from_str(&properties).unwrap_or_default(),
+            None => SpeciesProperties::default(),
+        };
+        write_attributes(conn, species_id, &properties)?;
+    }
+
+    Ok(())
+}
+
+fn attributes_from_row(row: &Row) -> Result<SpeciesAttributes> {
+    Ok(SpeciesAttributes {
+        species_id: row.get("id")?,
+        density_g_cm3: row.get("density_g_cm3")?,
+        density_min: row.get("density_min")?,
+        density_max: row.get("density_max")?,
+        xylem_porosity: row.get("xylem_porosity")?,
+        growth_ring_distinct: row.get("growth_ring_distinct")?,
+        height_m: row.get("height_m")?,
+        height_min_m: row.get("height_min_m")?,
+        height_max_m: row.get("height_max_m")?,
+        bark_thickness_mm: row.get("bark_thickness_mm")?,
+        bark_min_mm: row.get("bark_min_mm")?,
+        bark_max_mm: row.get("bark_max_mm")?,
+        deciduous: row.get("deciduous")?,
+        shade_tolerant: row.get("shade_tolerant")?,
+        flowering_months: Vec::new(),
+        fruiting_months: Vec::new(),
+    })
+}
+
+const SELECT_ATTRIBUTES: &str = "
+    SELECT s.id, w.*, t.*
+    FROM species s
+    LEFT JOIN wood_anatomy w ON w.species_id = s.id
+    LEFT JOIN tree_traits t ON t.species_id = s.id
+";
+
+fn add_phenology(attributes: &mut SpeciesAttributes, event: &str, month: u32) {
+    match event {
+        "flowering" => attributes.flowering_months.push(month),
+        _ => attributes.fruiting_months.push(month),
+    }
+}
+
+impl DbConnection {
+    /// Attributes of one species; species without recorded attributes get empty values
+    pub fn get_species_attributes(&self, species_id: i64) -> Result<SpeciesAttributes> {
+        let conn = self.conn.lock().unwrap();
+        let mut attributes = conn.query_row(
+            &format!("{} WHERE s.id = ?", SELECT_ATTRIBUTES),
+            params![species_id],
+            attributes_from_row,
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT event, month FROM species_phenology WHERE species_id = ? ORDER BY month"
+        )?;
+        let phenology = stmt.query_map(params![species_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?)))?;
+        for entry in phenology {
+            let (event, month) = entry?;
+            add_phenology(&mut attributes, &event, month);
+        }
+
+        Ok(attributes)
+    }
+
+    /// Attributes of every species, in species id order
+    pub fn list_species_attributes(&self) -> Result<Vec<SpeciesAttributes>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("{} ORDER BY s.id", SELECT_ATTRIBUTES))?;
+        let mut attributes = stmt.query_map([], attributes_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT species_id, event, month FROM species_phenology ORDER BY month")?;
+        let phenology = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?)))?;
+        for entry in phenology {
+            let (species_id, event, month) = entry?;
+            if let Ok(index) = attributes.binary_search_by_key(&species_id, |attributes| attributes.species_id) {
+                add_phenology(&mut attributes[index], &event, month);
+            }
+        }
+
+        Ok(attributes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_month_spans() {
+        assert_eq!(parse_months("April-June"), vec![4, 5, 6]);
+        assert_eq!(parse_months("November-February"), vec![1, 2, 11, 12]);
+        assert_eq!(parse_months("March, June-July"), vec![3, 6, 7]);
+        assert_eq!(parse_months("Sept"), vec![9]);
+        assert_eq!(parse_months("January-December (nearly continuous)"), (1..=12).collect::<Vec<_>>());
+        // En dash stored as mis-decoded UTF-8
+        assert_eq!(parse_months("April\u{e2}\u{20ac}\u{201c}May"), vec![4, 5]);
+        assert_eq!(parse_months("April\u{2013}May"), vec![4, 5]);
+        assert!(parse_months("After the rains").is_empty());
+    }
+
+    #[test]
+    fn year_round_periods_cover_every_month() {
+        for period in ["Year-round", "All year", "Flowers throughout the year", "year round (peaks in spring)"] {
+            assert_eq!(parse_months(period), (1..=12).collect::<Vec<_>>(), "{}", period);
+        }
+    }
+
+    #[test]
+    fn parses_numeric_ranges() {
+        assert_eq!(parse_range("0.60-0.70"), Some((0.6, 0.7)));
+        assert_eq!(parse_range(" 25 - 20 "), Some((20.0, 25.0)));
+        assert_eq!(parse_range("0.55\u{e2}\u{20ac}\u{201c}0.65"), Some((0.55, 0.65)));
+        assert_eq!(parse_range("12"), Some((12.0, 12.0)));
+        assert_eq!(parse_range("about 12"), None);
+        assert_eq!(parse_range("1-2-3"), None);
+    }
+}
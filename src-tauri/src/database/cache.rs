@@ -0,0 +1,158 @@
+use rusqlite::{OptionalExtension, Result, params};
+use serde::{Serialize, Deserialize};
+use super::DbConnection;
+
+pub(super) const CREATE_CACHE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS prediction_cache (
+        image_hash TEXT NOT NULL,
+        model_version TEXT NOT NULL,
+        prediction TEXT NOT NULL,
+        hit_count INTEGER NOT NULL DEFAULT 0,
+        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        last_used_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (image_hash, model_version)
+    )
+";
+
+/// Limits applied to the prediction cache whenever an entry is added
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CachePolicy {
+    /// Least recently used entries beyond this count are evicted
+    pub max_entries: i64,
+    /// Entries older than this are evicted regardless of use
+    pub max_age_days: i64,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy {
+            max_entries: 5000,
+            max_age_days: 180,
+        }
+    }
+}
+
+impl DbConnection {
+    /// Cached backend prediction (as JSON) for an image hash and model version, marking it as used.
+    /// Entries older than `policy` allows are ignored even before the next insert evicts them.
+    pub fn cached_prediction(&self, image_hash: &str, model_version: &str, policy: &CachePolicy) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let prediction = conn.query_row(
+            "SELECT prediction FROM prediction_cache
+             WHERE image_hash = ? AND model_version = ? AND created_at >= datetime('now', ?)",
+            params![image_hash, model_version, format!("-{} days", policy.max_age_days)],
+            |row| row.get::<_, String>(0),
+        ).optional()?;
+
+        if prediction.is_some() {
+            conn.execute(
+                "UPDATE prediction_cache
+                 SET hit_count = hit_count + 1, last_used_at = CURRENT_TIMESTAMP
+                 WHERE image_hash = ? AND model_version = ?",
+                params![image_hash, model_version],
+            )?;
+        }
+
+        Ok(prediction)
+    }
+
+    /// Store a backend prediction and evict entries outside `policy`
+    pub fn cache_prediction(&self, image_hash: &str, model_version: &str, prediction_json: &str, policy: &CachePolicy) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO prediction_cache (image_hash, model_version, prediction) VALUES (?, ?, ?)",
+            params![image_hash, model_version, prediction_json],
+        )?;
+
+        conn.execute(
+            "DELETE FROM prediction_cache WHERE created_at < datetime('now', ?)",
+            params![format!("-{} days", policy.max_age_days)],
+        )?;
+        conn.execute(
+            "DELETE FROM prediction_cache WHERE rowid NOT IN (
+                SELECT rowid FROM prediction_cache ORDER BY last_used_at DESC, rowid DESC LIMIT ?
+             )",
+            params![policy.max_entries],
+        )?;
+
+        Ok(())
+    }
+
+    /// Remove every cached prediction, returning how many were removed
+    pub fn clear_prediction_cache(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM prediction_cache", [])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached_hashes(db: &DbConnection) -> Vec<String> {
+        let conn = db.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT image_hash FROM prediction_cache ORDER BY image_hash").unwrap();
+        let hashes = stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<Vec<String>>>().unwrap();
+        hashes
+    }
+
+    fn set_timestamp(db: &DbConnection, column: &str, image_hash: &str, modifier: &str) {
+        let conn = db.conn.lock().unwrap();
+        conn.execute(
+            &format!("UPDATE prediction_cache SET {} = datetime('now', ?) WHERE image_hash = ?", column),
+            params![modifier, image_hash],
+        ).unwrap();
+    }
+
+    #[test]
+    fn entries_are_keyed_by_image_hash_and_model_version() {
+        let db = DbConnection::in_memory();
+        let policy = CachePolicy::default();
+        db.cache_prediction("hash-a", "local:v1", "{\"label\": \"Toona\"}", &policy).unwrap();
+        db.cache_prediction("hash-a", "local:v2", "{\"label\": \"Tectona\"}", &policy).unwrap();
+
+        assert_eq!(db.cached_prediction("hash-a", "local:v1", &policy).unwrap().as_deref(), Some("{\"label\": \"Toona\"}"));
+        assert_eq!(db.cached_prediction("hash-a", "local:v2", &policy).unwrap().as_deref(), Some("{\"label\": \"Tectona\"}"));
+        assert_eq!(db.cached_prediction("hash-b", "local:v1", &policy).unwrap(), None);
+        assert_eq!(db.cached_prediction("hash-a", "remote:v1", &policy).unwrap(), None);
+
+        let hits: i64 = db.conn.lock().unwrap().query_row(
+            "SELECT hit_count FROM prediction_cache WHERE image_hash = 'hash-a' AND model_version = 'local:v1'",
+            [],
+            |row| row.get(0),
+        ).unwrap();
+        assert_eq!(hits, 1);
+    }
+
+    #[test]
+    fn entries_past_the_age_limit_are_neither_served_nor_kept() {
+        let db = DbConnection::in_memory();
+        let policy = CachePolicy { max_entries: 10, max_age_days: 30 };
+        db.cache_prediction("hash-old", "local", "{}", &policy).unwrap();
+        db.cache_prediction("hash-new", "local", "{}", &policy).unwrap();
+        set_timestamp(&db, "created_at", "hash-old", "-31 days");
+
+        assert_eq!(db.cached_prediction("hash-old", "local", &policy).unwrap(), None);
+        assert!(db.cached_prediction("hash-new", "local", &policy).unwrap().is_some());
+        // A longer limit still serves it
+        assert!(db.cached_prediction("hash-old", "local", &CachePolicy { max_age_days: 60, ..policy }).unwrap().is_some());
+
+        db.cache_prediction("hash-next", "local", "{}", &policy).unwrap();
+        assert_eq!(cached_hashes(&db), vec!["hash-new", "hash-next"]);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entries_beyond_the_count_limit() {
+        let db = DbConnection::in_memory();
+        let policy = CachePolicy { max_entries: 2, max_age_days: 180 };
+        db.cache_prediction("hash-a", "local", "{}", &policy).unwrap();
+        db.cache_prediction("hash-b", "local", "{}", &policy).unwrap();
+        // "a" was added first but used more recently than "b"
+        set_timestamp(&db, "last_used_at", "hash-a", "-1 hours");
+        set_timestamp(&db, "last_used_at", "hash-b", "-2 hours");
+
+        db.cache_prediction("hash-c", "local", "{}", &policy).unwrap();
+        assert_eq!(cached_hashes(&db), vec!["hash-a", "hash-c"]);
+    }
+}
@@ -0,0 +1,68 @@
+use rusqlite::{OptionalExtension, Result, params};
+use serde::{Serialize, Deserialize};
+use super::DbConnection;
+
+pub(super) const CREATE_CALIBRATION_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS calibration (
+        model_version TEXT PRIMARY KEY,
+        method TEXT NOT NULL,
+        temperature REAL NOT NULL,
+        samples INTEGER NOT NULL,
+        nll_before REAL NOT NULL,
+        nll_after REAL NOT NULL,
+        fitted_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    )
+";
+
+/// Confidence calibration fitted for one model version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Calibration {
+    pub model_version: String,
+    pub method: String,
+    /// Probabilities are raised to `1 / temperature` and renormalized; above 1 softens overconfident scores
+    pub temperature: f64,
+    /// Labelled images used for the fit
+    pub samples: i64,
+    /// Mean negative log-likelihood of the true label before and after calibration
+    pub nll_before: f64,
+    pub nll_after: f64,
+    pub fitted_at: Option<String>,
+}
+
+impl DbConnection {
+    /// Calibration for a model version, if one has been fitted
+    pub fn calibration(&self, model_version: &str) -> Result<Option<Calibration>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT * FROM calibration WHERE model_version = ?",
+            params![model_version],
+            |row| Ok(Calibration {
+                model_version: row.get("model_version")?,
+                method: row.get("method")?,
+                temperature: row.get("temperature")?,
+                samples: row.get("samples")?,
+                nll_before: row.get("nll_before")?,
+                nll_after: row.get("nll_after")?,
+                fitted_at: row.get("fitted_at")?,
+            }),
+        ).optional()
+    }
+
+    /// Store a calibration, replacing any earlier fit for the same model version
+    pub fn save_calibration(&self, calibration: &Calibration) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO calibration (model_version, method, temperature, samples, nll_before, nll_after)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                calibration.model_version,
+                calibration.method,
+                calibration.temperature,
+                calibration.samples,
+                calibration.nll_before,
+                calibration.nll_after,
+            ],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use rusqlite::{Result, params_from_iter};
+use rusqlite::types::Value as SqlValue;
+use serde::{Serialize, Deserialize};
+use super::DbConnection;
+use super::species::{SPECIES_COLUMNS, Species, species_from_row};
+
+// Page size used when the caller does not set a limit
+const DEFAULT_FILTER_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum XylemPorosity {
+    DiffusePorous,
+    SemiRingPorous,
+    RingPorous,
+}
+
+impl XylemPorosity {
+    /// Value stored in `wood_anatomy.xylem_porosity`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            XylemPorosity::DiffusePorous => "diffuse-porous",
+            XylemPorosity::SemiRingPorous => "semi-ring-porous",
+            XylemPorosity::RingPorous => "ring-porous",
+        }
+    }
+}
+
+/// A single test on one attribute. Ranges match species whose recorded range overlaps `min..=max`;
+/// either bound may be left out.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "field", rename_all = "camelCase")]
+pub enum AttributeCondition {
+    /// g/cm³
+    Density { min: Option<f64>, max: Option<f64> },
+    /// Metres
+    Height { min: Option<f64>, max: Option<f64> },
+    /// Millimetres
+    BarkThickness { min: Option<f64>, max: Option<f64> },
+    #[serde(rename_all = "camelCase")]
+    XylemPorosity { any_of: Vec<XylemPorosity> },
+    GrowthRingDistinct { value: bool },
+    Deciduous { value: bool },
+    ShadeTolerant { value: bool },
+    /// Flowers in at least one of the months (1-12)
+    Flowering { months: Vec<u32> },
+    Fruiting { months: Vec<u32> },
+}
+
+/// A condition or a group of them, e.g. `{"any": [{"field": "deciduous", "value": true}, ...]}`.
+/// An empty `all` group matches everything and an empty `any` group matches nothing.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeQuery {
+    All { all: Vec<AttributeQuery> },
+    Any { any: Vec<AttributeQuery> },
+    Condition(AttributeCondition),
+}
+
+/// Criteria for finding species by attribute. Every query in `all` must match and, when `any` is
+/// not empty, at least one of `any` must match too.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeFilter {
+    #[serde(default)]
+    pub all: Vec<AttributeQuery>,
+    #[serde(default)]
+    pub any: Vec<AttributeQuery>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl From<AttributeCondition> for AttributeQuery {
+    fn from(condition: AttributeCondition) -> Self {
+        AttributeQuery::Condition(condition)
+    }
+}
+
+impl AttributeFilter {
+    /// Add a query that must match
+    pub fn and(mut self, query: impl Into<AttributeQuery>) -> Self {
+        self.all.push(query.into());
+        self
+    }
+
+    /// Add an alternative; at least one alternative must match
+    pub fn or(mut self, query: impl Into<AttributeQuery>) -> Self {
+        self.any.push(query.into());
+        self
+    }
+
+    fn query(&self) -> AttributeQuery {
+        let mut all = self.all.clone();
+        if !self.any.is_empty() {
+            all.push(AttributeQuery::Any { any: self.any.clone() });
+        }
+        AttributeQuery::All { all }
+    }
+}
+
+fn range_sql(min_column: &str, max_column: &str, min: Option<f64>, max: Option<f64>, values: &mut Vec<SqlValue>) -> String {
+    let mut bounds = Vec::new();
+    if let Some(min) = min {
+        bounds.push(format!("{} >= ?", max_column));
+        values.push(SqlValue::Real(min));
+    }
+    if let Some(max) = max {
+        bounds.push(format!("{} <= ?", min_column));
+        values.push(SqlValue::Real(max));
+    }
+    match bounds.is_empty() {
+        true => "1".to_string(),
+        false => format!("({})", bounds.join(" AND ")),
+    }
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+fn phenology_sql(event: &str, months: &[u32], values: &mut Vec<SqlValue>) -> String {
+    values.push(SqlValue::Text(event.to_string()));
+    values.extend(months.iter().map(|month| SqlValue::Integer(*month as i64)));
+    format!(
+        "s.id IN (SELECT species_id FROM species_phenology WHERE event = ? AND month IN ({}))",
+        placeholders(months.len()),
+    )
+}
+
+impl AttributeCondition {
+    fn to_sql(&self, values: &mut Vec<SqlValue>) -> String {
+        match self {
+            AttributeCondition::Density { min, max } => range_sql("w.density_min", "w.density_max", *min, *max, values),
+            AttributeCondition::Height { min, max } => range_sql("t.height_min_m", "t.height_max_m", *min, *max, values),
+            AttributeCondition::BarkThickness { min, max } => range_sql("t.bark_min_mm", "t.bark_max_mm", *min, *max, values),
+            AttributeCondition::XylemPorosity { any_of } => {
+                values.extend(any_of.iter().map(|porosity| SqlValue::Text(porosity.as_str().to_string())));
+                format!("w.xylem_porosity IN ({})", placeholders(any_of.len()))
+            }
+            AttributeCondition::GrowthRingDistinct { value } => {
+                values.push(SqlValue::Integer(*value as i64));
+                "w.growth_ring_distinct = ?".to_string()
+            }
+            AttributeCondition::Deciduous { value } => {
+                values.push(SqlValue::Integer(*value as i64));
+                "t.deciduous = ?".to_string()
+            }
+            AttributeCondition::ShadeTolerant { value } => {
+                values.push(SqlValue::Integer(*value as i64));
+                "t.shade_tolerant = ?".to_string()
+            }
+            AttributeCondition::Flowering { months } => phenology_sql("flowering", months, values),
+            AttributeCondition::Fruiting { months } => phenology_sql("fruiting", months, values),
+        }
+    }
+}
+
+impl AttributeQuery {
+    /// SQL expression over `species s`, `wood_anatomy w` and `tree_traits t`, with its parameters appended to `values`
+    fn to_sql(&self, values: &mut Vec<SqlValue>) -> String {
+        let (queries, separator, empty) = match self {
+            AttributeQuery::Condition(condition) => return condition.to_sql(values),
+            AttributeQuery::All { all } => (all, " AND ", "1"),
+            AttributeQuery::Any { any } => (any, " OR ", "0"),
+        };
+
+        match queries.is_empty() {
+            true => empty.to_string(),
+            false => format!(
+                "({})",
+                queries.iter().map(|query| query.to_sql(values)).collect::<Vec<_>>().join(separator),
+            ),
+        }
+    }
+}
+
+impl DbConnection {
+    /// Species matching `filter`, by scientific name
+    pub fn filter_species(&self, filter: &AttributeFilter) -> Result<Vec<Species>> {
+        let mut values: Vec<SqlValue> = Vec::new();
+        let condition = filter.query().to_sql(&mut values);
+
+        let columns = SPECIES_COLUMNS.split(", ")
+            .map(|column| format!("s.{}", column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = format!(
+            "SELECT {} FROM species s
+             LEFT JOIN wood_anatomy w ON w.species_id = s.id
+             LEFT JOIN tree_traits t ON t.species_id = s.id
+             WHERE {}
+             ORDER BY s.scientific_name LIMIT ? OFFSET ?",
+            columns, condition,
+        );
+        values.push(SqlValue::Integer(filter.limit.unwrap_or(DEFAULT_FILTER_LIMIT)));
+        values.push(SqlValue::Integer(filter.offset.unwrap_or(0)));
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params_from_iter(values.iter()), species_from_row)?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::attributes;
+
+    const SPECIES: &str = r#"
+        INSERT INTO species (scientific_name, common_name, family, description, properties) VALUES
+            ('Chukrasia tabularis', 'Chickrasi', 'Meliaceae', '',
+             '{"density_g_cm3": 0.65, "density_range": "0.60-0.70", "xylem_porosity": "diffuse-porous", "deciduous": true,
+               "flowering_time": "April-June"}'),
+            ('Tectona grandis', 'Segun', 'Lamiaceae', '',
+             '{"density_g_cm3": 0.66, "xylem_porosity": "ring-porous", "deciduous": true, "flowering_time": "June-August"}'),
+            ('Lagerstroemia speciosa', 'Jarul', 'Lythraceae', '',
+             '{"density_g_cm3": 0.85, "density_range": "0.80-0.90", "xylem_porosity": "semi-ring porous", "deciduous": false,
+               "flowering_time": "April-May"}');
+    "#;
+
+    fn database() -> DbConnection {
+        let db = DbConnection::in_memory();
+        {
+            let conn = db.conn.lock().unwrap();
+            conn.execute_batch(SPECIES).unwrap();
+            attributes::backfill(&conn).unwrap();
+        }
+        db
+    }
+
+    fn names(db: &DbConnection, filter: &AttributeFilter) -> Vec<String> {
+        db.filter_species(filter).unwrap().into_iter().map(|species| species.scientific_name).collect()
+    }
+
+    #[test]
+    fn combines_conditions_with_and() {
+        let filter = AttributeFilter::default()
+            .and(AttributeCondition::XylemPorosity { any_of: vec![XylemPorosity::DiffusePorous] })
+            .and(AttributeCondition::Density { min: Some(0.6), max: Some(0.8) })
+            .and(AttributeCondition::Deciduous { value: true });
+
+        assert_eq!(names(&database(), &filter), vec!["Chukrasia tabularis"]);
+    }
+
+    #[test]
+    fn combines_alternatives_with_or() {
+        let filter = AttributeFilter::default()
+            .and(AttributeCondition::Flowering { months: vec![4] })
+            .or(AttributeCondition::XylemPorosity { any_of: vec![XylemPorosity::SemiRingPorous] })
+            .or(AttributeCondition::Density { min: None, max: Some(0.62) });
+
+        assert_eq!(names(&database(), &filter), vec!["Chukrasia tabularis", "Lagerstroemia speciosa"]);
+    }
+
+    #[test]
+    fn parses_nested_groups_from_json() {
+        let filter: AttributeFilter = serde_json::from_str(r#"{
+            "all": [
+                {"field": "density", "min": 0.6},
+                {"any": [
+                    {"field": "xylemPorosity", "anyOf": ["ring-porous"]},
+                    {"all": [{"field": "deciduous", "value": false}, {"field": "flowering", "months": [5]}]}
+                ]}
+            ]
+        }"#).unwrap();
+
+        assert_eq!(names(&database(), &filter), vec!["Lagerstroemia speciosa", "Tectona grandis"]);
+    }
+
+    #[test]
+    fn empty_filter_lists_every_species() {
+        assert_eq!(names(&database(), &AttributeFilter::default()).len(), 3);
+    }
+}
@@ -0,0 +1,269 @@
+use rusqlite::{Result, params, params_from_iter};
+use rusqlite::types::Value as SqlValue;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use super::DbConnection;
+
+// `species_id` refers to the species database, which is a separate file, so it has no foreign key
+pub(super) const CREATE_ANALYSES_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS analyses (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        image_path TEXT NOT NULL,
+        image_hash TEXT NOT NULL,
+        label TEXT NOT NULL,
+        confidence REAL NOT NULL,
+        species_id INTEGER,
+        backend TEXT NOT NULL,
+        result TEXT NOT NULL,
+        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE INDEX IF NOT EXISTS idx_analyses_created_at ON analyses (created_at);
+";
+
+// Page size used when the caller does not set a limit
+const DEFAULT_HISTORY_LIMIT: i64 = 100;
+
+/// A new analysis to record in the history
+pub struct NewAnalysis<'a> {
+    pub image_path: &'a str,
+    pub image_hash: &'a str,
+    pub label: &'a str,
+    pub confidence: f64,
+    pub species_id: Option<i64>,
+    pub backend: &'a str,
+    pub result: &'a Value,
+}
+
+/// A stored analysis; `result` is only populated when a single record is reopened
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisRecord {
+    pub id: i64,
+    pub image_path: String,
+    pub image_hash: String,
+    pub label: String,
+    pub confidence: f64,
+    pub species_id: Option<i64>,
+    pub scientific_name: Option<String>,
+    pub backend: String,
+    pub created_at: String,
+    pub result: Option<Value>,
+}
+
+/// Criteria for listing past analyses; every field is optional and they are combined with AND
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisFilter {
+    /// Matches the label, scientific name or common name (case-insensitive substring)
+    pub species: Option<String>,
+    pub species_id: Option<i64>,
+    /// Inclusive dates, `YYYY-MM-DD`
+    pub from_date: Option<String>,
+    pub to_date: Option<String>,
+    pub min_confidence: Option<f64>,
+    pub max_confidence: Option<f64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+fn record_from_row(row: &rusqlite::Row, with_result: bool) -> Result<AnalysisRecord> {
+    let result = if with_result {
+        let result_json: String = row.get("result")?;
+        serde_json::from_str(&result_json).ok()
+    } else {
+        None
+    };
+
+    Ok(AnalysisRecord {
+        id: row.get("id")?,
+        image_path: row.get("image_path")?,
+        image_hash: row.get("image_hash")?,
+        label: row.get("label")?,
+        confidence: row.get("confidence")?,
+        species_id: row.get("species_id")?,
+        scientific_name: row.get("scientific_name")?,
+        backend: row.get("backend")?,
+        created_at: row.get("created_at")?,
+        result,
+    })
+}
+
+/// Escape LIKE wildcards for use with `ESCAPE '\'`
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+const SELECT_ANALYSES: &str = "
+    SELECT a.*, s.scientific_name
+    FROM analyses a
+    LEFT JOIN species s ON s.id = a.species_id
+";
+
+impl DbConnection {
+    pub fn insert_analysis(&self, analysis: &NewAnalysis) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO analyses (image_path, image_hash, label, confidence, species_id, backend, result)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                analysis.image_path,
+                analysis.image_hash,
+                analysis.label,
+                analysis.confidence,
+                analysis.species_id,
+                analysis.backend,
+                analysis.result.to_string(),
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Past analyses matching `filter`, newest first
+    pub fn list_analyses(&self, filter: &AnalysisFilter) -> Result<Vec<AnalysisRecord>> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<SqlValue> = Vec::new();
+
+        if let Some(species) = &filter.species {
+            conditions.push("(a.label LIKE ? ESCAPE '\\' OR s.scientific_name LIKE ? ESCAPE '\\' OR s.common_name LIKE ? ESCAPE '\\')");
+            // Match `%` and `_` literally; labels such as "Toona_ciliata" would otherwise match any character
+            let pattern = format!("%{}%", escape_like(species.trim()));
+            for _ in 0..3 {
+                values.push(SqlValue::Text(pattern.clone()));
+            }
+        }
+        if let Some(species_id) = filter.species_id {
+            conditions.push("a.species_id = ?");
+            values.push(SqlValue::Integer(species_id));
+        }
+        if let Some(from_date) = &filter.from_date {
+            conditions.push("date(a.created_at) >= date(?)");
+            values.push(SqlValue::Text(from_date.clone()));
+        }
+        if let Some(to_date) = &filter.to_date {
+            conditions.push("date(a.created_at) <= date(?)");
+            values.push(SqlValue::Text(to_date.clone()));
+        }
+        if let Some(min_confidence) = filter.min_confidence {
+            conditions.push("a.confidence >= ?");
+            values.push(SqlValue::Real(min_confidence));
+        }
+        if let Some(max_confidence) = filter.max_confidence {
+            conditions.push("a.confidence <= ?");
+            values.push(SqlValue::Real(max_confidence));
+        }
+
+        let mut query = SELECT_ANALYSES.to_string();
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+        query.push_str(" ORDER BY a.created_at DESC, a.id DESC LIMIT ? OFFSET ?");
+        values.push(SqlValue::Integer(filter.limit.unwrap_or(DEFAULT_HISTORY_LIMIT)));
+        values.push(SqlValue::Integer(filter.offset.unwrap_or(0)));
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params_from_iter(values.iter()), |row| record_from_row(row, false))?;
+        rows.collect()
+    }
+
+    /// A single analysis including its full stored result
+    pub fn get_analysis(&self, id: i64) -> Result<AnalysisRecord> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("{} WHERE a.id = ?", SELECT_ANALYSES),
+            params![id],
+            |row| record_from_row(row, true),
+        )
+    }
+
+    /// Returns false if no analysis had that id
+    pub fn delete_analysis(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows_affected = conn.execute("DELETE FROM analyses WHERE id = ?", params![id])?;
+        Ok(rows_affected > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn database() -> DbConnection {
+        let db = DbConnection::in_memory();
+        db.conn.lock().unwrap().execute_batch("
+            INSERT INTO species (id, scientific_name, common_name, family, description) VALUES
+                (1, 'Toona ciliata', 'Toon, Rangi', 'Meliaceae', ''),
+                (2, 'Tectona grandis', 'Segun', 'Lamiaceae', '');
+        ").unwrap();
+        db
+    }
+
+    fn insert(db: &DbConnection, label: &str, confidence: f64, species_id: Option<i64>, created_at: &str) -> i64 {
+        let id = db.insert_analysis(&NewAnalysis {
+            image_path: "/images/sample.jpg",
+            image_hash: "hash",
+            label,
+            confidence,
+            species_id,
+            backend: "local",
+            result: &json!({ "label": label, "confidence": confidence }),
+        }).unwrap();
+        db.conn.lock().unwrap().execute("UPDATE analyses SET created_at = ? WHERE id = ?", params![created_at, id]).unwrap();
+        id
+    }
+
+    fn listed(db: &DbConnection, filter: AnalysisFilter) -> Vec<i64> {
+        db.list_analyses(&filter).unwrap().iter().map(|record| record.id).collect()
+    }
+
+    #[test]
+    fn lists_newest_first_with_combined_filters() {
+        let db = database();
+        let toona = insert(&db, "Toona_ciliata_Toon", 0.9, Some(1), "2024-03-01 10:00:00");
+        let tectona = insert(&db, "Tectona_grandis_Segun", 0.6, Some(2), "2024-03-05 10:00:00");
+        let unknown = insert(&db, "Unknown", 0.3, None, "2024-04-01 10:00:00");
+
+        assert_eq!(listed(&db, AnalysisFilter::default()), vec![unknown, tectona, toona]);
+        // Scientific and common names come from the joined species record
+        assert_eq!(listed(&db, AnalysisFilter { species: Some("toona CILIATA".to_string()), ..Default::default() }), vec![toona]);
+        assert_eq!(listed(&db, AnalysisFilter { species: Some(" rangi ".to_string()), ..Default::default() }), vec![toona]);
+        assert_eq!(listed(&db, AnalysisFilter { species_id: Some(2), ..Default::default() }), vec![tectona]);
+        assert_eq!(listed(&db, AnalysisFilter {
+            from_date: Some("2024-03-01".to_string()),
+            to_date: Some("2024-03-05".to_string()),
+            ..Default::default()
+        }), vec![tectona, toona]);
+        assert_eq!(listed(&db, AnalysisFilter { min_confidence: Some(0.5), max_confidence: Some(0.8), ..Default::default() }), vec![tectona]);
+        assert_eq!(listed(&db, AnalysisFilter { limit: Some(1), offset: Some(1), ..Default::default() }), vec![tectona]);
+    }
+
+    #[test]
+    fn matches_like_wildcards_literally() {
+        let db = database();
+        let underscored = insert(&db, "Toona_ciliata", 0.9, None, "2024-03-01 10:00:00");
+        let spaced = insert(&db, "Toona ciliata", 0.9, None, "2024-03-02 10:00:00");
+        let percent = insert(&db, "Grade 100% heartwood", 0.9, None, "2024-03-03 10:00:00");
+
+        assert_eq!(listed(&db, AnalysisFilter { species: Some("Toona_".to_string()), ..Default::default() }), vec![underscored]);
+        assert_eq!(listed(&db, AnalysisFilter { species: Some("Toona c".to_string()), ..Default::default() }), vec![spaced]);
+        assert_eq!(listed(&db, AnalysisFilter { species: Some("%".to_string()), ..Default::default() }), vec![percent]);
+        assert!(listed(&db, AnalysisFilter { species: Some("\\".to_string()), ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn reopens_and_deletes_single_records() {
+        let db = database();
+        let id = insert(&db, "Toona_ciliata_Toon", 0.9, Some(1), "2024-03-01 10:00:00");
+
+        let record = db.get_analysis(id).unwrap();
+        assert_eq!(record.scientific_name.as_deref(), Some("Toona ciliata"));
+        assert_eq!(record.result, Some(json!({ "label": "Toona_ciliata_Toon", "confidence": 0.9 })));
+        // Listings leave the stored result out
+        assert!(db.list_analyses(&AnalysisFilter::default()).unwrap()[0].result.is_none());
+
+        assert!(db.delete_analysis(id).unwrap());
+        assert!(!db.delete_analysis(id).unwrap());
+        assert!(matches!(db.get_analysis(id), Err(rusqlite::Error::QueryReturnedNoRows)));
+    }
+}
@@ -0,0 +1,365 @@
+use rusqlite::{Connection, params};
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use super::DbConnection;
+use super::attributes::write_attributes;
+use super::species::{SpeciesProperties, TreeTraits, WoodAnatomy};
+
+/// One species as written in `species_data.json`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct SpeciesRecord {
+    #[serde(default)]
+    pub scientific_name: String,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub local_name: Vec<String>,
+    #[serde(default)]
+    pub usages: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub wood_anatomy: WoodAnatomy,
+    #[serde(flatten)]
+    pub traits: TreeTraits,
+}
+
+impl SpeciesRecord {
+    pub fn properties(&self) -> SpeciesProperties {
+        SpeciesProperties {
+            wood_anatomy: self.wood_anatomy.clone(),
+            traits: self.traits.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeciesDataLayout {
+    /// `{"Chukrasia tabularis": {...}}`, as in `src-tauri/resources/species_data.json`
+    KeyedMap,
+    /// `[{"scientific_name": "Chukrasia tabularis", ...}]`, as in `backend/species_data.json`
+    Array,
+}
+
+/// Records recognized in a species data file and the entries that had to be left out
+#[derive(Debug, Clone)]
+pub struct SpeciesDataset {
+    pub layout: SpeciesDataLayout,
+    pub records: Vec<SpeciesRecord>,
+    /// Why each unrecognized entry was skipped
+    pub skipped: Vec<String>,
+}
+
+/// Outcome of importing a species data file
+#[derive(Debug, Clone)]
+pub struct ImportReport {
+    pub layout: SpeciesDataLayout,
+    pub recognized: usize,
+    pub skipped: Vec<String>,
+    pub species: usize,
+    pub labels: usize,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> String {
+        let layout = match self.layout {
+            SpeciesDataLayout::KeyedMap => "keyed map",
+            SpeciesDataLayout::Array => "array",
+        };
+        format!(
+            "Recognized {} species records ({} layout), skipped {}; wrote {} species with {} labels",
+            self.recognized, layout, self.skipped.len(), self.species, self.labels,
+        )
+    }
+}
+
+fn parse_record(entry: Value, name: Option<&str>) -> Result<SpeciesRecord, String> {
+    if !entry.is_object() {
+        return Err("not a JSON object".to_string());
+    }
+    let mut record: SpeciesRecord = serde_json::from_value(entry).map_err(|e| e.to_string())?;
+
+    // The keyed layout names the species by its key rather than a field
+    if record.scientific_name.trim().is_empty() {
+        record.scientific_name = name.unwrap_or_default().to_string();
+    }
+    record.scientific_name = record.scientific_name.trim().to_string();
+    match record.scientific_name.is_empty() {
+        true => Err("no scientific_name".to_string()),
+        false => Ok(record),
+    }
+}
+
+/// Parse species data in either the keyed-map or the array layout
+pub fn parse_species_data(json: &str) -> Result<SpeciesDataset, serde_json::Error> {
+    let (layout, entries): (SpeciesDataLayout, Vec<(String, Option<String>, Value)>) = match serde_json::from_str(json)? {
+        Value::Object(map) => (SpeciesDataLayout::KeyedMap, map.into_iter()
+            .map(|(name, entry)| (format!("'{}'", name), Some(name), entry))
+            .collect()),
+        Value::Array(items) => (SpeciesDataLayout::Array, items.into_iter()
+            .enumerate()
+            .map(|(index, entry)| (format!("entry {}", index), None, entry))
+            .collect()),
+        _ => return Err(serde::de::Error::custom("species data must be a JSON object or array")),
+    };
+
+    let mut dataset = SpeciesDataset { layout, records: Vec::new(), skipped: Vec::new() };
+    for (position, name, entry) in entries {
+        match parse_record(entry, name.as_deref()) {
+            Ok(record) => dataset.records.push(record),
+            Err(reason) => dataset.skipped.push(format!("{}: {}", position, reason)),
+        }
+    }
+
+    Ok(dataset)
+}
+
+/// Insert or update one species with its model labels and attribute rows; returns the labels written.
+/// Labels are `"Scientific name_Local name"` for every local name, or the bare scientific name without any.
+fn upsert_species(conn: &Connection, record: &SpeciesRecord) -> Result<usize, Box<dyn Error>> {
+    let common_name = match record.local_name.is_empty() {
+        true => record.scientific_name.clone(),
+        false => record.local_name.join(", "),
+    };
+    let properties = record.properties();
+
+    conn.execute(
+        "INSERT INTO species
+         (scientific_name, common_name, family, description, properties, uses)
+         VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT (scientific_name) DO UPDATE SET
+            common_name = excluded.common_name,
+            family = excluded.family,
+            description = excluded.description,
+            properties = excluded.properties,
+            uses = excluded.uses,
+            updated_at = CURRENT_TIMESTAMP",
+        params![
+            record.scientific_name,
+            common_name,
+            record.family,
+            record.notes,
+            serde_json::to_string(&properties)?,
+            serde_json::to_string(&record.usages)?,
+        ],
+    )?;
+
+    // Re-imports update the existing row, so look the id up rather than using the last rowid
+    let species_id: i64 = conn.query_row(
+        "SELECT id FROM species WHERE scientific_name = ?",
+        params![record.scientific_name],
+        |row| row.get(0),
+    )?;
+
+    // Keep the queryable attribute tables in step with the properties JSON
+    write_attributes(conn, species_id, &properties)?;
+
+    let labels: Vec<String> = match record.local_name.is_empty() {
+        true => vec![record.scientific_name.clone()],
+        false => record.local_name.iter()
+            .map(|local_name| format!("{}_{}", record.scientific_name, local_name))
+            .collect(),
+    };
+    for label in &labels {
+        conn.execute(
+            "INSERT INTO model_labels (species_id, label) VALUES (?, ?)
+             ON CONFLICT (label) DO UPDATE SET species_id = excluded.species_id",
+            params![species_id, label],
+        )?;
+    }
+
+    Ok(labels.len())
+}
+
+impl DbConnection {
+    /// Parse a species data file in either layout and write its records; used by the app on first
+    /// start and by the `import_species` CLI so both produce the same rows and labels
+    pub fn import_species_file(&self, json_path: &str) -> Result<ImportReport, Box<dyn Error>> {
+        let json_content = fs::read_to_string(json_path)?;
+        let dataset = parse_species_data(&json_content)?;
+        let (species, labels) = self.import_species_records(&dataset.records)?;
+
+        Ok(ImportReport {
+            layout: dataset.layout,
+            recognized: dataset.records.len(),
+            skipped: dataset.skipped,
+            species,
+            labels,
+        })
+    }
+
+    /// Write parsed species records in one transaction and rebuild the search index.
+    /// Returns the number of species and model labels written.
+    pub fn import_species_records(&self, records: &[SpeciesRecord]) -> Result<(usize, usize), Box<dyn Error>> {
+        let mut labels = 0;
+        {
+            let mut conn = self.conn.lock().unwrap();
+            let tx = conn.transaction()?;
+            for record in records {
+                labels += upsert_species(&tx, record)?;
+            }
+            tx.commit()?;
+        }
+
+        // Keep full-text search and label resolution in step with the imported records
+        self.rebuild_search_index()?;
+        self.invalidate_label_index();
+        Ok((records.len(), labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(db: &DbConnection, query: &str) -> Vec<Vec<String>> {
+        let conn = db.conn.lock().unwrap();
+        let mut stmt = conn.prepare(query).unwrap();
+        let columns = stmt.column_count();
+        let rows = stmt.query_map([], |row| (0..columns).map(|index| row.get::<_, String>(index)).collect()).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn parses_both_layouts_alike() {
+        let keyed = parse_species_data(include_str!("../../resources/species_data.json")).unwrap();
+        let array = parse_species_data(include_str!("../../../backend/species_data.json")).unwrap();
+
+        assert_eq!(keyed.layout, SpeciesDataLayout::KeyedMap);
+        assert_eq!(array.layout, SpeciesDataLayout::Array);
+        assert_eq!(keyed.records.len(), 21);
+        assert_eq!(array.records.len(), 21);
+        assert!(keyed.skipped.is_empty() && array.skipped.is_empty());
+
+        let chukrasia = array.records.iter().find(|record| record.scientific_name == "Chukrasia tabularis").unwrap();
+        assert_eq!(chukrasia.local_name[0], "Chickrasi");
+        assert_eq!(chukrasia.properties().wood_anatomy.density_range.as_deref(), Some("0.60-0.70"));
+        assert_eq!(chukrasia.traits.deciduous, Some(true));
+    }
+
+    #[test]
+    fn both_layouts_import_identical_rows() {
+        let keyed = parse_species_data(r#"{
+            "Toona ciliata": {"family": "Meliaceae", "local_name": ["Toon", "Toona"], "usages": ["Furniture"],
+                              "notes": "Fast growing", "deciduous": true, "wood_anatomy": {"density_g_cm3": 0.45}}
+        }"#).unwrap();
+        let array = parse_species_data(r#"[
+            {"scientific_name": "Toona ciliata", "family": "Meliaceae", "local_name": ["Toon", "Toona"], "usages": ["Furniture"],
+             "notes": "Fast growing", "deciduous": true, "wood_anatomy": {"density_g_cm3": 0.45}}
+        ]"#).unwrap();
+
+        let (keyed_db, array_db) = (DbConnection::in_memory(), DbConnection::in_memory());
+        assert_eq!(keyed_db.import_species_records(&keyed.records).unwrap(), (1, 2));
+        assert_eq!(array_db.import_species_records(&array.records).unwrap(), (1, 2));
+
+        let species = "SELECT scientific_name, common_name, family, description, properties, uses FROM species";
+        let labels = "SELECT label, CAST(species_id AS TEXT) FROM model_labels ORDER BY label";
+        assert_eq!(rows(&keyed_db, species), rows(&array_db, species));
+        assert_eq!(rows(&keyed_db, labels), rows(&array_db, labels));
+
+        let toona = keyed_db.get_species_by_label("Toona ciliata_Toona").unwrap();
+        assert_eq!(toona.common_name, "Toon, Toona");
+        assert_eq!(toona.uses, vec!["Furniture"]);
+    }
+
+    #[test]
+    fn reimporting_updates_in_place() {
+        let db = DbConnection::in_memory();
+        let dataset = parse_species_data(include_str!("../../resources/species_data.json")).unwrap();
+        db.import_species_records(&dataset.records).unwrap();
+        db.import_species_records(&dataset.records).unwrap();
+
+        assert_eq!(db.list_species().unwrap().len(), 21);
+        assert_eq!(db.search_species("Chickrasi", None, None).unwrap().total, 1);
+        assert!(!db.get_species_attributes(1).unwrap().flowering_months.is_empty());
+    }
+
+    #[test]
+    fn skips_unrecognized_entries() {
+        let dataset = parse_species_data(r#"[
+            {"scientific_name": "Toona ciliata", "family": "Meliaceae"},
+            {"family": "Meliaceae"},
+            "Tectona grandis"
+        ]"#).unwrap();
+
+        assert_eq!(dataset.records.len(), 1);
+        assert_eq!(dataset.skipped, vec!["entry 1: no scientific_name", "entry 2: not a JSON object"]);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(parse_species_data("42").is_err());
+    }
+
+    /// Numbers compare by value, since `25` in the data file is written back as `25.0`
+    fn same_value(source: &Value, stored: &Value) -> bool {
+        match (source.as_f64(), stored.as_f64()) {
+            (Some(source), Some(stored)) => source == stored,
+            _ => source == stored,
+        }
+    }
+
+    #[test]
+    fn keeps_every_property_of_the_shipped_data() {
+        const RECORD_FIELDS: [&str; 6] = ["scientific_name", "family", "local_name", "usages", "notes", "wood_anatomy"];
+
+        for json in [include_str!("../../resources/species_data.json"), include_str!("../../../backend/species_data.json")] {
+            let entries: Vec<Value> = match serde_json::from_str(json).unwrap() {
+                Value::Object(map) => map.into_iter().map(|(_, entry)| entry).collect(),
+                Value::Array(items) => items,
+                _ => unreachable!(),
+            };
+
+            for entry in entries {
+                let record = parse_record(entry.clone(), Some("keyed")).unwrap();
+                let stored = serde_json::to_value(record.properties()).unwrap();
+
+                let wood_anatomy = entry["wood_anatomy"].as_object().cloned().unwrap_or_default();
+                let traits = entry.as_object().unwrap().iter()
+                    .filter(|(key, _)| !RECORD_FIELDS.contains(&key.as_str()))
+                    .map(|(key, value)| (key.clone(), value.clone()));
+
+                for (key, value) in wood_anatomy.into_iter().chain(traits) {
+                    assert!(same_value(&value, &stored[&key]), "{}: {} was {}, stored {}", record.scientific_name, key, value, stored[&key]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_a_species_through_import_and_lookup() {
+        let db = DbConnection::in_memory();
+        db.import_species_file(concat!(env!("CARGO_MANIFEST_DIR"), "/../backend/species_data.json")).unwrap();
+
+        let species = db.get_species_by_label("Swietenia mahagoni_Mahogoni").unwrap();
+        assert_eq!((species.family.as_str(), species.common_name.as_str()), ("Meliaceae", "Mahogoni"));
+        assert!(species.description.starts_with("Swietenia mahagoni is a species of Swietenia"));
+        assert_eq!(species.uses.len(), 5);
+
+        let wood = &species.properties.wood_anatomy;
+        assert_eq!((wood.density_g_cm3, wood.density_range.as_deref()), (Some(0.6), Some("0.55-0.65")));
+        assert_eq!(wood.wood_color.as_deref(), Some("Reddish-brown"));
+        assert_eq!(wood.grain.as_deref(), Some("Straight to interlocked"));
+        assert_eq!(wood.texture.as_deref(), Some("Medium to coarse"));
+
+        let traits = &species.properties.traits;
+        assert_eq!((traits.shade_tolerant, traits.shade_intolerant, traits.deciduous), (Some(true), Some(false), Some(true)));
+        assert_eq!((traits.tree_height_m, traits.bark_thickness_range.as_deref()), (Some(25.0), Some("8-12")));
+        assert_eq!((traits.flowering_time.as_deref(), traits.fruiting_time.as_deref()), (Some("March-April"), Some("June-August")));
+
+        // The frontend reads the properties flat
+        let properties = serde_json::to_value(&species.properties).unwrap();
+        assert_eq!(properties["grain"], "Straight to interlocked");
+        assert_eq!(properties["xylem_porosity"], "diffuse-porous");
+    }
+
+    #[test]
+    fn startup_load_reports_the_import_error() {
+        let db = DbConnection::in_memory();
+        let error = db.ensure_species_data_loaded("does-not-exist/species_data.json").unwrap_err();
+        let io_error = error.downcast_ref::<std::io::Error>().expect("the file error is passed through");
+        assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,49 @@
+use rusqlite::{Result, params};
+use serde::Serialize;
+use std::collections::HashMap;
+use super::DbConnection;
+use super::resolver::normalize_label;
+
+/// A species no model class maps to
+#[derive(Debug, Clone, Serialize)]
+pub struct UnlabelledSpecies {
+    pub id: i64,
+    pub scientific_name: String,
+}
+
+impl DbConnection {
+    /// Per-label minimum confidence from `model_labels.confidence_threshold`, keyed by normalized label
+    /// so backend spellings such as `Tectona_grandis_Segun` find the row for `Tectona grandis_Segun`
+    pub fn label_thresholds(&self) -> Result<HashMap<String, f64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT label, confidence_threshold FROM model_labels WHERE confidence_threshold IS NOT NULL"
+        )?;
+        let thresholds = stmt.query_map([], |row| Ok((normalize_label(&row.get::<_, String>(0)?), row.get::<_, f64>(1)?)))?
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(thresholds)
+    }
+
+    /// Map a label to a species, replacing a row that points at a deleted species
+    pub fn create_model_label(&self, label: &str, species_id: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO model_labels (species_id, label) VALUES (?, ?)",
+            params![species_id, label],
+        )?;
+        self.invalidate_label_index();
+        Ok(())
+    }
+
+    /// Species other than `species_ids`, the species the model's classes resolve to
+    pub fn species_without_labels(&self, species_ids: &[i64]) -> Result<Vec<UnlabelledSpecies>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT id, scientific_name FROM species ORDER BY scientific_name")?;
+        let species = stmt.query_map([], |row| Ok(UnlabelledSpecies {
+            id: row.get(0)?,
+            scientific_name: row.get(1)?,
+        }))?.collect::<Result<Vec<_>>>()?;
+
+        Ok(species.into_iter().filter(|entry| !species_ids.contains(&entry.id)).collect())
+    }
+}
@@ -0,0 +1,440 @@
+use rusqlite::{Connection, Result, Transaction, params};
+use super::{attributes, cache, calibration, history, queue, search, species};
+
+/// One schema change; `version` is stored in `PRAGMA user_version` once it has been applied
+struct Migration {
+    version: i32,
+    description: &'static str,
+    apply: fn(&Transaction) -> Result<()>,
+}
+
+/// Species database, shipped with the app
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "species schema from backend/schema.sql", apply: species_schema },
+    Migration { version: 2, description: "species search index", apply: search_index },
+    Migration { version: 3, description: "wood anatomy and tree trait tables", apply: attribute_tables },
+    Migration { version: 4, description: "rows written by the old startup species loader", apply: startup_loader_rows },
+];
+
+/// App data database, created in the app data directory; versioned separately from the species database
+const APP_DATA_MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "app tables", apply: app_tables },
+];
+
+/// Bring a species database of any earlier layout up to the latest migration
+pub(super) fn migrate(conn: &mut Connection) -> Result<()> {
+    apply(conn, MIGRATIONS)
+}
+
+/// Bring an app data database up to the latest migration
+pub(super) fn migrate_app_data(conn: &mut Connection) -> Result<()> {
+    apply(conn, APP_DATA_MIGRATIONS)
+}
+
+/// Apply the migrations newer than the database's `user_version`, one transaction per migration
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<()> {
+    let current: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for migration in migrations.iter().filter(|migration| migration.version > current) {
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", migration.version))?;
+        tx.commit()?;
+        eprintln!("Applied database migration {}: {}", migration.version, migration.description);
+    }
+
+    Ok(())
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(columns.iter().any(|name| name == column))
+}
+
+fn add_column_if_missing(tx: &Transaction, table: &str, column: &str, definition: &str) -> Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+    }
+    Ok(())
+}
+
+/// Databases have been created by `backend/schema.sql`, by `rebuild_database.py` (same layout) and by
+/// `import_species.rs` (no UNIQUE constraints, timestamps or `confidence_threshold`). Bring them all to
+/// the schema.sql layout. Constraints SQLite cannot add to an existing table become unique indexes.
+fn species_schema(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("
+        CREATE TABLE IF NOT EXISTS species (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            scientific_name TEXT NOT NULL UNIQUE,
+            common_name TEXT NOT NULL,
+            family TEXT NOT NULL,
+            description TEXT NOT NULL,
+            habitat TEXT,
+            distribution TEXT,
+            properties TEXT,
+            uses TEXT,
+            conservation_status TEXT,
+            image_url TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS model_labels (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            label TEXT NOT NULL UNIQUE,
+            species_id INTEGER NOT NULL,
+            confidence_threshold REAL DEFAULT 0.5,
+            FOREIGN KEY (species_id) REFERENCES species(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS activation_keys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            key_hash TEXT NOT NULL UNIQUE,
+            is_used BOOLEAN DEFAULT FALSE,
+            used_by TEXT,
+            used_at TIMESTAMP,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+    ")?;
+
+    // ALTER TABLE cannot add a column with a non-constant default such as CURRENT_TIMESTAMP
+    add_column_if_missing(tx, "species", "created_at", "TIMESTAMP")?;
+    add_column_if_missing(tx, "species", "updated_at", "TIMESTAMP")?;
+    add_column_if_missing(tx, "model_labels", "confidence_threshold", "REAL DEFAULT 0.5")?;
+
+    tx.execute_batch("
+        UPDATE species SET
+            common_name = COALESCE(common_name, ''),
+            family = COALESCE(family, ''),
+            description = COALESCE(description, '')
+        WHERE common_name IS NULL OR family IS NULL OR description IS NULL;
+
+        UPDATE model_labels SET confidence_threshold = 0.5 WHERE confidence_threshold IS NULL;
+
+        -- Re-running the old importer appended duplicate species; keep the newest and move labels to it
+        UPDATE model_labels SET species_id = (
+            SELECT MAX(keep.id)
+            FROM species keep
+            JOIN species old ON old.scientific_name = keep.scientific_name
+            WHERE old.id = model_labels.species_id
+        )
+        WHERE species_id IN (SELECT id FROM species);
+
+        DELETE FROM species WHERE id NOT IN (SELECT MAX(id) FROM species GROUP BY scientific_name);
+        DELETE FROM model_labels WHERE id NOT IN (SELECT MAX(id) FROM model_labels GROUP BY label);
+
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_species_scientific_name ON species (scientific_name);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_model_labels_label ON model_labels (label);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_activation_keys_key_hash ON activation_keys (key_hash);
+    ")
+}
+
+/// Tables written by the app itself rather than shipped with the species data
+fn app_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(queue::CREATE_QUEUE_TABLE)?;
+    tx.execute_batch(history::CREATE_ANALYSES_TABLE)?;
+    tx.execute_batch(cache::CREATE_CACHE_TABLE)?;
+    tx.execute_batch(calibration::CREATE_CALIBRATION_TABLE)
+}
+
+fn search_index(tx: &Transaction) -> Result<()> {
+    search::rebuild(tx)
+}
+
+/// Until now these attributes only existed inside the `species.properties` JSON
+fn attribute_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(attributes::CREATE_ATTRIBUTE_TABLES)?;
+    attributes::backfill(tx)
+}
+
+/// The startup loader stored `uses` as comma-separated text and linked every label to the species
+/// with id 1. Store `uses` as the JSON array the importer writes and relink labels by scientific name.
+fn startup_loader_rows(tx: &Transaction) -> Result<()> {
+    let comma_separated: Vec<(i64, String)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, uses FROM species
+             WHERE uses IS NOT NULL AND (json_valid(uses) = 0 OR json_type(uses) != 'array')"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_>>()?
+    };
+    for (id, uses) in comma_separated {
+        let uses = serde_json::to_string(&species::parse_uses(&uses))
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        tx.execute("UPDATE species SET uses = ? WHERE id = ?", params![uses, id])?;
+    }
+
+    tx.execute_batch("
+        UPDATE model_labels SET species_id = (
+            SELECT s.id FROM species s
+            WHERE model_labels.label = s.scientific_name
+               OR substr(model_labels.label, 1, length(s.scientific_name) + 1) = s.scientific_name || '_'
+            ORDER BY length(s.scientific_name) DESC
+            LIMIT 1
+        )
+        WHERE NOT EXISTS (
+            SELECT 1 FROM species s
+            WHERE s.id = model_labels.species_id
+              AND (model_labels.label = s.scientific_name
+                   OR substr(model_labels.label, 1, length(s.scientific_name) + 1) = s.scientific_name || '_')
+        )
+        AND EXISTS (
+            SELECT 1 FROM species s
+            WHERE model_labels.label = s.scientific_name
+               OR substr(model_labels.label, 1, length(s.scientific_name) + 1) = s.scientific_name || '_'
+        );
+    ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DbConnection;
+    use std::path::{Path, PathBuf};
+
+    // Tables as created by `import_species.rs` before migrations existed
+    const IMPORT_SPECIES_LAYOUT: &str = "
+        CREATE TABLE species (
+            id INTEGER PRIMARY KEY,
+            scientific_name TEXT NOT NULL,
+            common_name TEXT NOT NULL,
+            family TEXT NOT NULL,
+            description TEXT,
+            habitat TEXT,
+            distribution TEXT,
+            properties TEXT,
+            uses TEXT,
+            conservation_status TEXT,
+            image_url TEXT
+        );
+        CREATE TABLE model_labels (
+            id INTEGER PRIMARY KEY,
+            species_id INTEGER NOT NULL,
+            label TEXT NOT NULL,
+            FOREIGN KEY (species_id) REFERENCES species (id)
+        );
+    ";
+
+    const SCHEMA_SQL_LAYOUT: &str = include_str!("../../../backend/schema.sql");
+
+    fn latest_version() -> i32 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    fn user_version(conn: &Connection) -> i32 {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap()
+    }
+
+    fn count(conn: &Connection, query: &str) -> i64 {
+        conn.query_row(query, [], |row| row.get(0)).unwrap()
+    }
+
+    fn assert_current_schema(conn: &Connection) {
+        assert_eq!(user_version(conn), latest_version());
+        for table in ["species", "model_labels", "activation_keys", "species_fts", "wood_anatomy", "tree_traits", "species_phenology"] {
+            let exists = count(conn, &format!("SELECT COUNT(*) FROM sqlite_master WHERE name = '{}'", table));
+            assert_eq!(exists, 1, "missing table {}", table);
+        }
+        assert_eq!(
+            count(conn, "SELECT COUNT(*) FROM species_fts"),
+            count(conn, "SELECT COUNT(*) FROM species"),
+        );
+    }
+
+    /// Copy a database into the temp directory so the checked-in file is never modified
+    fn temp_copy(source: &Path, name: &str) -> PathBuf {
+        let target = std::env::temp_dir().join(format!("treescope-migration-{}-{}.db", std::process::id(), name));
+        std::fs::copy(source, &target).unwrap();
+        target
+    }
+
+    #[test]
+    fn upgrades_import_species_layout() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(IMPORT_SPECIES_LAYOUT).unwrap();
+        conn.execute_batch("
+            INSERT INTO species (id, scientific_name, common_name, family) VALUES (1, 'Toona ciliata', 'Toon', 'Meliaceae');
+            INSERT INTO model_labels (species_id, label) VALUES (1, 'Toona ciliata_Toon');
+            -- a second import of the same file
+            INSERT INTO species (id, scientific_name, common_name, family) VALUES (2, 'Toona ciliata', 'Toon', 'Meliaceae');
+            INSERT INTO model_labels (species_id, label) VALUES (2, 'Toona ciliata_Toon');
+            INSERT INTO model_labels (species_id, label) VALUES (1, 'Toona ciliata_Toona');
+        ").unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_current_schema(&conn);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM species"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM model_labels"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM model_labels WHERE species_id != 2"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM model_labels WHERE confidence_threshold = 0.5"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM species WHERE description = ''"), 1);
+
+        let duplicate = conn.execute("INSERT INTO model_labels (species_id, label) VALUES (2, 'Toona ciliata_Toon')", []);
+        assert!(duplicate.is_err(), "labels must be unique after migration");
+    }
+
+    #[test]
+    fn upgrades_schema_sql_layout() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_SQL_LAYOUT).unwrap();
+        conn.execute_batch("
+            INSERT INTO species (scientific_name, common_name, family, description) VALUES ('Tectona grandis', 'Segun', 'Lamiaceae', 'Teak');
+            INSERT INTO model_labels (species_id, label, confidence_threshold) VALUES (1, 'Tectona grandis_Segun', 0.7);
+        ").unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_current_schema(&conn);
+        let threshold: f64 = conn.query_row("SELECT confidence_threshold FROM model_labels", [], |row| row.get(0)).unwrap();
+        assert_eq!(threshold, 0.7);
+    }
+
+    #[test]
+    fn backfills_attributes_from_properties() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_SQL_LAYOUT).unwrap();
+        conn.execute_batch(r#"
+            INSERT INTO species (scientific_name, common_name, family, description, properties) VALUES (
+                'Tectona grandis', 'Segun', 'Lamiaceae', 'Teak',
+                '{"density_g_cm3": 0.65, "density_range": "0.60-0.70", "xylem_porosity": "Ring-porous", "growth_ring": "distinct",
+                  "tree_height_m": 30, "bark_thickness_mm": 10, "bark_thickness_range": "9-11", "shade_tolerant": false,
+                  "shade_intolerant": true, "flowering_time": "June-August", "fruiting_time": "November-January", "deciduous": true}'
+            );
+        "#).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let row: (f64, f64, String, bool, f64, f64, bool, bool) = conn.query_row(
+            "SELECT w.density_min, w.density_max, w.xylem_porosity, w.growth_ring_distinct,
+                    t.height_min_m, t.bark_max_mm, t.deciduous, t.shade_tolerant
+             FROM wood_anatomy w JOIN tree_traits t USING (species_id)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?)),
+        ).unwrap();
+        assert_eq!(row, (0.6, 0.7, "ring-porous".to_string(), true, 30.0, 11.0, true, false));
+
+        let fruiting: Vec<u32> = conn.prepare("SELECT month FROM species_phenology WHERE event = 'fruiting' ORDER BY month").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(fruiting, vec![1, 11, 12]);
+    }
+
+    #[test]
+    fn creates_fresh_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_current_schema(&conn);
+    }
+
+    #[test]
+    fn repairs_startup_loader_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA_SQL_LAYOUT).unwrap();
+        conn.execute_batch("
+            INSERT INTO species (id, scientific_name, common_name, family, description, uses) VALUES
+                (1, 'Toona ciliata', 'Toon', 'Meliaceae', '', 'Furniture, Boats'),
+                (2, 'Tectona grandis', 'Segun', 'Lamiaceae', '', '[\"Veneer\"]');
+            INSERT INTO model_labels (species_id, label) VALUES
+                (1, 'Toona ciliata'),
+                (1, 'Tectona grandis'),
+                (1, 'Tectona grandis_Segun'),
+                (1, 'Unmatched_Label');
+        ").unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let uses: Vec<String> = conn.prepare("SELECT uses FROM species ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(uses, vec![r#"["Furniture","Boats"]"#, r#"["Veneer"]"#]);
+
+        let linked = |label: &str| -> i64 {
+            conn.query_row("SELECT species_id FROM model_labels WHERE label = ?", params![label], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(linked("Toona ciliata"), 1);
+        assert_eq!(linked("Tectona grandis"), 2);
+        assert_eq!(linked("Tectona grandis_Segun"), 2);
+        assert_eq!(linked("Unmatched_Label"), 1);
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(IMPORT_SPECIES_LAYOUT).unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute_batch("PRAGMA user_version = 0").unwrap();
+        migrate(&mut conn).unwrap();
+        assert_current_schema(&conn);
+    }
+
+    #[test]
+    fn upgrades_shipped_databases() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let shipped = [
+            ("resources", manifest_dir.join("resources/species.db")),
+            ("species-data", manifest_dir.join("../species_data/species.db")),
+            ("src-resources", manifest_dir.join("src/resources/species.db")),
+        ];
+
+        for (name, source) in shipped {
+            let path = temp_copy(&source, name);
+            let db = DbConnection::new(path.to_string_lossy().to_string()).unwrap();
+
+            {
+                let conn = db.conn.lock().unwrap();
+                assert_current_schema(&conn);
+            }
+
+            let species = db.get_species_by_label("Chukrasia tabularis_Chickrasi").unwrap();
+            assert_eq!(species.scientific_name, "Chukrasia tabularis", "{}", name);
+            assert!(!db.label_thresholds().unwrap().is_empty(), "{}", name);
+
+            let attributes = db.get_species_attributes(species.id).unwrap();
+            assert_eq!(attributes.density_min, Some(0.6), "{}", name);
+            assert_eq!(attributes.flowering_months, vec![4, 5, 6], "{}", name);
+
+            drop(db);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_app_tables_in_the_app_data_database() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let species_path = temp_copy(&manifest_dir.join("resources/species.db"), "attached-species");
+        let app_path = std::env::temp_dir().join(format!("treescope-migration-{}-attached-app.db", std::process::id()));
+        let _ = std::fs::remove_file(&app_path);
+
+        let db = DbConnection::with_app_data(species_path.to_string_lossy().to_string(), &app_path.to_string_lossy()).unwrap();
+        db.save_calibration(&crate::database::Calibration {
+            model_version: "local".to_string(),
+            method: "temperature".to_string(),
+            temperature: 1.5,
+            samples: 10,
+            nll_before: 1.0,
+            nll_after: 0.8,
+            fitted_at: None,
+        }).unwrap();
+        assert_eq!(db.get_species_by_label("Chukrasia tabularis_Chickrasi").unwrap().scientific_name, "Chukrasia tabularis");
+        drop(db);
+
+        let species = Connection::open(&species_path).unwrap();
+        assert_eq!(user_version(&species), latest_version());
+        for table in ["analysis_queue", "analyses", "prediction_cache", "calibration"] {
+            let exists = count(&species, &format!("SELECT COUNT(*) FROM sqlite_master WHERE name = '{}'", table));
+            assert_eq!(exists, 0, "{} written to the species database", table);
+        }
+
+        let app = Connection::open(&app_path).unwrap();
+        assert_eq!(user_version(&app), APP_DATA_MIGRATIONS.last().unwrap().version);
+        assert_eq!(count(&app, "SELECT COUNT(*) FROM calibration WHERE model_version = 'local'"), 1);
+        assert_eq!(count(&app, "SELECT COUNT(*) FROM analyses"), 0);
+
+        drop((species, app));
+        std::fs::remove_file(species_path).unwrap();
+        std::fs::remove_file(app_path).unwrap();
+    }
+}
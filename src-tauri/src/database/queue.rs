@@ -0,0 +1,177 @@
+use rusqlite::{OptionalExtension, Result, params};
+use serde::Serialize;
+use super::DbConnection;
+
+pub(super) const CREATE_QUEUE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS analysis_queue (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        image_path TEXT NOT NULL,
+        image_hash TEXT NOT NULL,
+        top_k INTEGER NOT NULL,
+        tiled BOOLEAN NOT NULL DEFAULT 0,
+        status TEXT NOT NULL DEFAULT 'pending',
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        result TEXT,
+        queued_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+        completed_at TIMESTAMP
+    )
+";
+
+/// An image waiting for the classifier to become reachable
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedAnalysis {
+    pub id: i64,
+    pub image_path: String,
+    pub image_hash: String,
+    /// Options of the original request; the rest come from the settings current when it is processed
+    pub top_k: usize,
+    pub tiled: bool,
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub queued_at: String,
+}
+
+impl DbConnection {
+    /// Queue an image for later analysis, reusing the pending entry if the same image is already queued.
+    /// A pending entry for the same path whose file has since changed is superseded by the new one.
+    pub fn enqueue_analysis(&self, image_path: &str, image_hash: &str, top_k: usize, tiled: bool) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE analysis_queue SET status = 'superseded', completed_at = CURRENT_TIMESTAMP
+             WHERE image_path = ? AND image_hash != ? AND status = 'pending'",
+            params![image_path, image_hash],
+        )?;
+
+        let existing = conn.query_row(
+            "SELECT id FROM analysis_queue WHERE image_hash = ? AND image_path = ? AND status = 'pending'",
+            params![image_hash, image_path],
+            |row| row.get::<_, i64>(0),
+        ).optional()?;
+        if let Some(id) = existing {
+            // The latest request decides the options
+            conn.execute(
+                "UPDATE analysis_queue SET top_k = ?, tiled = ? WHERE id = ?",
+                params![top_k, tiled, id],
+            )?;
+            return Ok(id);
+        }
+
+        conn.execute(
+            "INSERT INTO analysis_queue (image_path, image_hash, top_k, tiled) VALUES (?, ?, ?, ?)",
+            params![image_path, image_hash, top_k, tiled],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Pending entries, oldest first
+    pub fn pending_analyses(&self) -> Result<Vec<QueuedAnalysis>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, image_path, image_hash, top_k, tiled, status, attempts, last_error, queued_at
+             FROM analysis_queue
+             WHERE status = 'pending'
+             ORDER BY queued_at, id",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(QueuedAnalysis {
+                id: row.get("id")?,
+                image_path: row.get("image_path")?,
+                image_hash: row.get("image_hash")?,
+                top_k: row.get("top_k")?,
+                tiled: row.get("tiled")?,
+                status: row.get("status")?,
+                attempts: row.get("attempts")?,
+                last_error: row.get("last_error")?,
+                queued_at: row.get("queued_at")?,
+            })
+        })?;
+
+        rows.collect()
+    }
+
+    pub fn complete_queued_analysis(&self, id: i64, result_json: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE analysis_queue
+             SET status = 'completed', attempts = attempts + 1, result = ?, last_error = NULL, completed_at = CURRENT_TIMESTAMP
+             WHERE id = ?",
+            params![result_json, id],
+        )?;
+        Ok(())
+    }
+
+    /// Count an attempt that found the classifier unreachable again; the entry stays pending
+    pub fn retry_queued_analysis(&self, id: i64, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE analysis_queue SET attempts = attempts + 1, last_error = ? WHERE id = ? AND status = 'pending'",
+            params![error, id],
+        )?;
+        Ok(())
+    }
+
+    /// Mark an entry that cannot succeed on retry, e.g. the file was deleted or is not a valid image
+    pub fn fail_queued_analysis(&self, id: i64, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE analysis_queue
+             SET status = 'failed', attempts = attempts + 1, last_error = ?, completed_at = CURRENT_TIMESTAMP
+             WHERE id = ?",
+            params![error, id],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempts(db: &DbConnection, id: i64) -> (String, i64) {
+        let conn = db.conn.lock().unwrap();
+        conn.query_row("SELECT status, attempts FROM analysis_queue WHERE id = ?", params![id], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+    }
+
+    #[test]
+    fn reuses_the_pending_entry_with_the_latest_options() {
+        let db = DbConnection::in_memory();
+        let first = db.enqueue_analysis("/images/a.jpg", "hash-a", 5, false).unwrap();
+        let second = db.enqueue_analysis("/images/a.jpg", "hash-a", 3, true).unwrap();
+        assert_eq!(first, second);
+
+        let pending = db.pending_analyses().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].top_k, pending[0].tiled), (3, true));
+    }
+
+    #[test]
+    fn supersedes_the_pending_entry_when_the_image_changes() {
+        let db = DbConnection::in_memory();
+        let old = db.enqueue_analysis("/images/a.jpg", "hash-a", 5, false).unwrap();
+        let new = db.enqueue_analysis("/images/a.jpg", "hash-b", 5, false).unwrap();
+        assert_ne!(old, new);
+
+        assert_eq!(attempts(&db, old).0, "superseded");
+        let pending = db.pending_analyses().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].image_hash, "hash-b");
+    }
+
+    #[test]
+    fn counts_every_attempt() {
+        let db = DbConnection::in_memory();
+        let id = db.enqueue_analysis("/images/a.jpg", "hash-a", 5, false).unwrap();
+
+        db.retry_queued_analysis(id, "unreachable").unwrap();
+        db.retry_queued_analysis(id, "unreachable").unwrap();
+        assert_eq!(attempts(&db, id), ("pending".to_string(), 2));
+        assert_eq!(db.pending_analyses().unwrap()[0].last_error.as_deref(), Some("unreachable"));
+
+        db.complete_queued_analysis(id, "{}").unwrap();
+        assert_eq!(attempts(&db, id), ("completed".to_string(), 3));
+    }
+}
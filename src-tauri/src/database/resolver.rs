@@ -0,0 +1,274 @@
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use super::{DbConnection, Species};
+
+/// How a label was matched to a species, strongest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchStrategy {
+    /// The label has a `model_labels` row
+    ModelLabel,
+    /// The label is a scientific name, or starts with one followed by a local name
+    ScientificName,
+    /// The label, or its part after the first underscore, is one of the species' local names
+    Synonym,
+}
+
+/// A species a label could refer to
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelCandidate {
+    pub species_id: i64,
+    pub scientific_name: String,
+    pub strategy: MatchStrategy,
+}
+
+/// Outcome of resolving a label; `candidates` lists every match, strongest strategy first
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelResolution {
+    pub label: String,
+    pub normalized: String,
+    pub species_id: i64,
+    pub strategy: MatchStrategy,
+    pub candidates: Vec<LabelCandidate>,
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    NotFound(String),
+    /// More than one species matched at the strongest strategy that matched at all
+    Ambiguous { label: String, candidates: Vec<LabelCandidate> },
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::NotFound(label) => write!(f, "Species information for '{}' not found in the database", label),
+            ResolveError::Ambiguous { label, candidates } => {
+                let names: Vec<&str> = candidates.iter().map(|candidate| candidate.scientific_name.as_str()).collect();
+                write!(f, "Label '{}' is ambiguous between {}", label, names.join(", "))
+            },
+            ResolveError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl From<rusqlite::Error> for ResolveError {
+    fn from(e: rusqlite::Error) -> Self {
+        ResolveError::Database(e)
+    }
+}
+
+/// Lowercase, treat underscores and hyphens as spaces and collapse whitespace
+pub fn normalize_label(label: &str) -> String {
+    label.replace(['_', '-'], " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+struct SpeciesNames {
+    id: i64,
+    scientific_name: String,
+    /// `scientific_name` normalized
+    normalized_name: String,
+    /// Normalized local names
+    common_names: Vec<String>,
+}
+
+/// Every model label and species name, normalized once and shared by all `resolve_label` calls
+pub(super) struct LabelIndex {
+    /// Normalized label and the species it is mapped to
+    model_labels: Vec<(String, i64)>,
+    species: Vec<SpeciesNames>,
+}
+
+impl LabelIndex {
+    fn load(conn: &Connection) -> Result<Self> {
+        let mut stmt = conn.prepare("SELECT label, species_id FROM model_labels")?;
+        let model_labels = stmt.query_map([], |row| Ok((normalize_label(&row.get::<_, String>(0)?), row.get::<_, i64>(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT id, scientific_name, common_name FROM species ORDER BY id")?;
+        let species = stmt.query_map([], |row| {
+            let scientific_name: String = row.get(1)?;
+            Ok(SpeciesNames {
+                id: row.get(0)?,
+                normalized_name: normalize_label(&scientific_name),
+                scientific_name,
+                common_names: row.get::<_, Option<String>>(2)?
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(normalize_label)
+                    .filter(|name| !name.is_empty())
+                    .collect(),
+            })
+        })?.collect::<Result<Vec<_>>>()?;
+
+        Ok(LabelIndex { model_labels, species })
+    }
+}
+
+impl DbConnection {
+    fn label_index(&self) -> Result<Arc<LabelIndex>> {
+        let mut cached = self.label_index.lock().unwrap();
+        if let Some(index) = cached.as_ref() {
+            return Ok(index.clone());
+        }
+
+        let index = Arc::new(LabelIndex::load(&self.conn.lock().unwrap())?);
+        *cached = Some(index.clone());
+        Ok(index)
+    }
+
+    /// Drop the cached label lookup tables; call after writing species or model labels
+    pub(super) fn invalidate_label_index(&self) {
+        *self.label_index.lock().unwrap() = None;
+    }
+
+    /// Resolve a model label to exactly one species using exact, normalized matches only
+    pub fn resolve_label(&self, label: &str) -> std::result::Result<LabelResolution, ResolveError> {
+        let normalized = normalize_label(label);
+        // Model labels are "Scientific name_Local name", though some backends use underscores throughout
+        let local_part = label.split_once('_').map(|(_, local)| normalize_label(local));
+
+        let index = self.label_index()?;
+
+        let mut candidates: Vec<LabelCandidate> = Vec::new();
+        let mut add = |species: &SpeciesNames, strategy: MatchStrategy| {
+            if !candidates.iter().any(|candidate| candidate.species_id == species.id) {
+                candidates.push(LabelCandidate {
+                    species_id: species.id,
+                    scientific_name: species.scientific_name.clone(),
+                    strategy,
+                });
+            }
+        };
+
+        for (model_label, species_id) in &index.model_labels {
+            if *model_label == normalized {
+                if let Some(entry) = index.species.iter().find(|entry| entry.id == *species_id) {
+                    add(entry, MatchStrategy::ModelLabel);
+                }
+            }
+        }
+
+        for entry in &index.species {
+            let is_prefix = normalized.strip_prefix(&entry.normalized_name).is_some_and(|rest| rest.starts_with(' '));
+            if entry.normalized_name == normalized || is_prefix {
+                add(entry, MatchStrategy::ScientificName);
+            }
+        }
+
+        for entry in &index.species {
+            let matches_local_name = entry.common_names.iter()
+                .any(|name| *name == normalized || Some(name) == local_part.as_ref());
+            if matches_local_name {
+                add(entry, MatchStrategy::Synonym);
+            }
+        }
+
+        let strategy = match candidates.first() {
+            Some(best) => best.strategy,
+            None => return Err(ResolveError::NotFound(label.to_string())),
+        };
+
+        let strongest: Vec<&LabelCandidate> = candidates.iter().filter(|candidate| candidate.strategy == strategy).collect();
+        if strongest.len() > 1 {
+            return Err(ResolveError::Ambiguous {
+                label: label.to_string(),
+                candidates: strongest.into_iter().cloned().collect(),
+            });
+        }
+
+        Ok(LabelResolution {
+            label: label.to_string(),
+            normalized,
+            species_id: strongest[0].species_id,
+            strategy,
+            candidates,
+        })
+    }
+
+    /// Species record for a model label, resolved with `resolve_label`
+    pub fn get_species_by_label(&self, label: &str) -> std::result::Result<Species, ResolveError> {
+        let resolution = self.resolve_label(label)?;
+        Ok(self.get_species(resolution.species_id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> DbConnection {
+        let db = DbConnection::in_memory();
+        db.conn.lock().unwrap().execute_batch("
+            INSERT INTO species (id, scientific_name, common_name, family, description) VALUES
+                (1, 'Toona ciliata', 'Toon, Rangi', 'Meliaceae', ''),
+                (2, 'Cedrela toona', 'Toon', 'Meliaceae', ''),
+                (3, 'Tectona grandis', 'Segun', 'Lamiaceae', '');
+            INSERT INTO model_labels (species_id, label) VALUES (1, 'Teak_Special');
+        ").unwrap();
+        db
+    }
+
+    fn resolved(db: &DbConnection, label: &str) -> (i64, MatchStrategy) {
+        let resolution = db.resolve_label(label).unwrap();
+        (resolution.species_id, resolution.strategy)
+    }
+
+    #[test]
+    fn normalizes_separators_case_and_spacing() {
+        assert_eq!(normalize_label("Tectona_grandis_Segun"), "tectona grandis segun");
+        assert_eq!(normalize_label("  Tectona   GRANDIS-segun "), "tectona grandis segun");
+        assert_eq!(normalize_label(""), "");
+    }
+
+    #[test]
+    fn prefers_model_labels_then_scientific_names_then_synonyms() {
+        let db = database();
+
+        assert_eq!(resolved(&db, "teak special"), (1, MatchStrategy::ModelLabel));
+        assert_eq!(resolved(&db, "Tectona_grandis"), (3, MatchStrategy::ScientificName));
+        assert_eq!(resolved(&db, "Tectona_grandis_Teak"), (3, MatchStrategy::ScientificName));
+        assert!(matches!(db.resolve_label("Tectona grandiflora"), Err(ResolveError::NotFound(_))));
+        assert_eq!(resolved(&db, "Segun"), (3, MatchStrategy::Synonym));
+
+        // The scientific name wins over a local name of another species, which is still listed
+        let resolution = db.resolve_label("Tectona grandis_Rangi").unwrap();
+        assert_eq!((resolution.species_id, resolution.strategy), (3, MatchStrategy::ScientificName));
+        let candidates: Vec<(i64, MatchStrategy)> = resolution.candidates.iter()
+            .map(|candidate| (candidate.species_id, candidate.strategy))
+            .collect();
+        assert_eq!(candidates, vec![(3, MatchStrategy::ScientificName), (1, MatchStrategy::Synonym)]);
+    }
+
+    #[test]
+    fn reports_ambiguous_and_unknown_labels() {
+        let db = database();
+
+        match db.resolve_label("Unknown_Toon") {
+            Err(ResolveError::Ambiguous { candidates, .. }) => {
+                let ids: Vec<i64> = candidates.iter().map(|candidate| candidate.species_id).collect();
+                assert_eq!(ids, vec![1, 2]);
+            },
+            other => panic!("expected an ambiguous label, got {:?}", other),
+        }
+        assert!(matches!(db.resolve_label("Swietenia mahagoni"), Err(ResolveError::NotFound(_))));
+    }
+
+    #[test]
+    fn sees_labels_created_after_the_first_lookup() {
+        let db = database();
+        assert!(matches!(db.resolve_label("Unknown_Toon"), Err(ResolveError::Ambiguous { .. })));
+
+        db.create_model_label("Unknown_Toon", 2).unwrap();
+        assert_eq!(resolved(&db, "Unknown_Toon"), (2, MatchStrategy::ModelLabel));
+    }
+}
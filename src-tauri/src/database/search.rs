@@ -0,0 +1,238 @@
+use rusqlite::{Connection, Result, params};
+use serde::Serialize;
+use super::DbConnection;
+
+// Full-text index over species text; the rowid is the species id
+const CREATE_SEARCH_TABLE: &str = "
+    CREATE VIRTUAL TABLE IF NOT EXISTS species_fts USING fts5(
+        scientific_name,
+        local_names,
+        family,
+        uses,
+        notes,
+        tokenize = 'unicode61 remove_diacritics 2'
+    )
+";
+
+// Column weights for bm25, in table column order: names count far more than notes
+const RANK: &str = "bm25(species_fts, 10.0, 8.0, 4.0, 2.0, 1.0)";
+
+// Page size used when the caller does not set a limit
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+
+/// Create the index if needed and refill it from the species table.
+/// `uses` is indexed as its values joined, so the JSON punctuation never shows up in snippets;
+/// text that is not a JSON array yet (before the startup loader migration) is indexed as it is.
+pub(super) fn rebuild(conn: &Connection) -> Result<()> {
+    conn.execute_batch(CREATE_SEARCH_TABLE)?;
+    conn.execute_batch(
+        "DELETE FROM species_fts;
+         INSERT INTO species_fts (rowid, scientific_name, local_names, family, uses, notes)
+         SELECT id, scientific_name, common_name, family,
+                CASE WHEN json_valid(uses) AND json_type(uses) = 'array'
+                     THEN (SELECT group_concat(value, ', ') FROM json_each(species.uses))
+                     ELSE uses
+                END,
+                description
+         FROM species;"
+    )
+}
+
+/// Turn free text into an FTS5 query: every word must match, as a prefix, in any column.
+/// Quoting each word keeps user input from being parsed as FTS5 syntax.
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+
+    match terms.is_empty() {
+        true => None,
+        false => Some(terms.join(" ")),
+    }
+}
+
+/// A matching species with search terms wrapped in `<mark>` tags
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeciesHit {
+    pub id: i64,
+    pub scientific_name: String,
+    pub common_name: String,
+    pub family: String,
+    /// bm25 score; lower is a better match
+    pub rank: f64,
+    pub highlighted_name: String,
+    pub highlighted_local_names: String,
+    pub highlighted_family: String,
+    /// Excerpt of the best matching column around the matched terms
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeciesSearchPage {
+    pub query: String,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub results: Vec<SpeciesHit>,
+}
+
+impl DbConnection {
+    /// Recreate the search index from the species table; called whenever species data is imported
+    pub fn rebuild_search_index(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        rebuild(&conn)
+    }
+
+    /// Ranked full-text search over names, family, uses and notes
+    pub fn search_species(&self, query: &str, limit: Option<i64>, offset: Option<i64>) -> Result<SpeciesSearchPage> {
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).max(1);
+        let offset = offset.unwrap_or(0).max(0);
+
+        let mut page = SpeciesSearchPage {
+            query: query.to_string(),
+            total: 0,
+            limit,
+            offset,
+            results: Vec::new(),
+        };
+
+        let expression = match match_expression(query) {
+            Some(expression) => expression,
+            None => return Ok(page),
+        };
+
+        let conn = self.conn.lock().unwrap();
+        page.total = conn.query_row(
+            "SELECT COUNT(*) FROM species_fts WHERE species_fts MATCH ?",
+            params![expression],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT s.id, s.scientific_name, s.common_name, s.family, {rank} AS rank,
+                    highlight(species_fts, 0, '<mark>', '</mark>'),
+                    highlight(species_fts, 1, '<mark>', '</mark>'),
+                    highlight(species_fts, 2, '<mark>', '</mark>'),
+                    snippet(species_fts, -1, '<mark>', '</mark>', '…', 16)
+             FROM species_fts
+             JOIN species s ON s.id = species_fts.rowid
+             WHERE species_fts MATCH ?
+             ORDER BY rank, s.scientific_name
+             LIMIT ? OFFSET ?",
+            rank = RANK,
+        ))?;
+
+        page.results = stmt.query_map(params![expression, limit, offset], |row| Ok(SpeciesHit {
+            id: row.get(0)?,
+            scientific_name: row.get(1)?,
+            common_name: row.get(2)?,
+            family: row.get(3)?,
+            rank: row.get(4)?,
+            highlighted_name: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+            highlighted_local_names: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+            highlighted_family: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+            snippet: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        }))?.collect::<Result<Vec<_>>>()?;
+
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> DbConnection {
+        let db = DbConnection::in_memory();
+        db.conn.lock().unwrap().execute_batch(r#"
+            INSERT INTO species (id, scientific_name, common_name, family, description, uses) VALUES
+                (1, 'Toona ciliata', 'Toon, Rangi', 'Meliaceae', 'Lighter than Tectona and easily worked.', '["Furniture","Boats"]'),
+                (2, 'Tectona grandis', 'Segun, Teak', 'Lamiaceae', 'Durable heartwood.', '["Boat building"]'),
+                (3, 'Swietenia mahagoni', 'Mahogany', 'Meliaceae', '', 'Veneer, Plywood'),
+                (4, 'Chukrasia tabularis', 'Chickrassy', 'Meliaceae', '', NULL);
+        "#).unwrap();
+        db.rebuild_search_index().unwrap();
+        db
+    }
+
+    fn ids(page: &SpeciesSearchPage) -> Vec<i64> {
+        page.results.iter().map(|hit| hit.id).collect()
+    }
+
+    #[test]
+    fn ranks_name_matches_above_notes_and_highlights_them() {
+        let db = database();
+        let page = db.search_species("tectona", None, None).unwrap();
+
+        assert_eq!(ids(&page), vec![2, 1]);
+        assert!(page.results[0].rank < page.results[1].rank);
+        assert_eq!(page.results[0].highlighted_name, "<mark>Tectona</mark> grandis");
+        assert_eq!(page.results[1].highlighted_name, "Toona ciliata");
+        assert!(page.results[1].snippet.contains("<mark>Tectona</mark>"));
+
+        // Words match as prefixes in any column, and every word has to match
+        let page = db.search_species("tea", None, None).unwrap();
+        assert_eq!(page.results[0].highlighted_local_names, "Segun, <mark>Teak</mark>");
+        assert_eq!(ids(&db.search_species("meliaceae rangi", None, None).unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn indexes_the_values_of_uses() {
+        let db = database();
+        let indexed: Vec<Option<String>> = db.conn.lock().unwrap()
+            .prepare("SELECT uses FROM species_fts ORDER BY rowid").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(indexed, vec![
+            Some("Furniture, Boats".to_string()),
+            Some("Boat building".to_string()),
+            Some("Veneer, Plywood".to_string()),
+            None,
+        ]);
+
+        let page = db.search_species("boats", None, None).unwrap();
+        assert_eq!(ids(&page), vec![1]);
+        assert_eq!(page.results[0].snippet, "Furniture, <mark>Boats</mark>");
+    }
+
+    #[test]
+    fn pages_through_results_with_the_total_count() {
+        let db = database();
+        let all = db.search_species("meliaceae", None, None).unwrap();
+        assert_eq!(all.total, 3);
+        assert_eq!(all.results.len(), 3);
+
+        let page = db.search_species("meliaceae", Some(2), Some(1)).unwrap();
+        assert_eq!((page.total, page.limit, page.offset), (3, 2, 1));
+        assert_eq!(ids(&page), ids(&all)[1..].to_vec());
+
+        let past_the_end = db.search_species("meliaceae", Some(2), Some(5)).unwrap();
+        assert_eq!(past_the_end.total, 3);
+        assert!(past_the_end.results.is_empty());
+
+        // Nonsensical paging is clamped
+        let clamped = db.search_species("meliaceae", Some(0), Some(-3)).unwrap();
+        assert_eq!((clamped.limit, clamped.offset, clamped.results.len()), (1, 0, 1));
+    }
+
+    #[test]
+    fn treats_fts_syntax_in_queries_as_plain_words() {
+        let db = database();
+        for query in ["\"teak", "teak\"", "-teak", "teak*", "(teak)", "teak:", "^teak"] {
+            let page = db.search_species(query, None, None).unwrap_or_else(|e| panic!("{:?} failed: {}", query, e));
+            assert_eq!(ids(&page), vec![2], "query {:?}", query);
+        }
+
+        // Operators are searched for as words rather than combining the others
+        assert!(db.search_species("teak NEAR segun", None, None).unwrap().results.is_empty());
+        assert!(db.search_species("teak AND NOT segun", None, None).unwrap().results.is_empty());
+        assert!(db.search_species("teak OR mahogany", None, None).unwrap().results.is_empty());
+
+        for query in ["", "\"", "*", "- -", "NEAR("] {
+            let page = db.search_species(query, None, None).unwrap();
+            assert!(page.results.is_empty(), "query {:?}", query);
+        }
+    }
+}
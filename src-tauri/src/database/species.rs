@@ -0,0 +1,133 @@
+use rusqlite::{Result, Row, params};
+use serde::{Serialize, Deserialize};
+use super::DbConnection;
+
+/// Columns read by `species_from_row`, selected by name so a renamed or missing column fails the query
+pub(super) const SPECIES_COLUMNS: &str =
+    "id, scientific_name, common_name, family, description, habitat, distribution, properties, uses, conservation_status, image_url";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WoodAnatomy {
+    pub density_g_cm3: Option<f64>,
+    pub density_range: Option<String>,
+    pub xylem_porosity: Option<String>,
+    pub growth_ring: Option<String>,
+    pub wood_color: Option<String>,
+    pub grain: Option<String>,
+    pub texture: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TreeTraits {
+    pub tree_height_m: Option<f64>,
+    pub tree_height_range: Option<String>,
+    pub bark_thickness_mm: Option<f64>,
+    pub bark_thickness_range: Option<String>,
+    pub shade_tolerant: Option<bool>,
+    pub shade_intolerant: Option<bool>,
+    pub flowering_time: Option<String>,
+    pub fruiting_time: Option<String>,
+    pub deciduous: Option<bool>,
+}
+
+/// Contents of the `species.properties` JSON column. Serialized flat, as the frontend expects.
+/// Flattening rules out `deny_unknown_fields`, so the import tests check that no key of the
+/// shipped species data is dropped.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeciesProperties {
+    #[serde(flatten)]
+    pub wood_anatomy: WoodAnatomy,
+    #[serde(flatten)]
+    pub traits: TreeTraits,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Species {
+    pub id: i64,
+    pub scientific_name: String,
+    /// Comma-separated local names
+    pub common_name: String,
+    pub family: String,
+    pub description: String,
+    pub habitat: Option<String>,
+    pub distribution: Option<String>,
+    pub properties: SpeciesProperties,
+    pub uses: Vec<String>,
+    pub conservation_status: Option<String>,
+    pub image_url: Option<String>,
+}
+
+/// `uses` is a JSON array when written by the importer and a comma-separated list otherwise
+pub(super) fn parse_uses(uses: &str) -> Vec<String> {
+    match serde_json::from_str::<Vec<String>>(uses) {
+        Ok(uses) => uses,
+        Err(_) => uses.split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(|item| item.to_string())
+            .collect(),
+    }
+}
+
+pub(super) fn species_from_row(row: &Row) -> Result<Species> {
+    let properties = match row.get::<_, Option<String>>("properties")? {
+        // One malformed record should not hide the species, as in `attributes::backfill`
+        Some(properties) if !properties.trim().is_empty() => serde_json::from_str(&properties).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable properties of species {}: {}", row.get::<_, i64>("id").unwrap_or_default(), e);
+            SpeciesProperties::default()
+        }),
+        _ => SpeciesProperties::default(),
+    };
+
+    Ok(Species {
+        id: row.get("id")?,
+        scientific_name: row.get("scientific_name")?,
+        common_name: row.get("common_name")?,
+        family: row.get("family")?,
+        description: row.get::<_, Option<String>>("description")?.unwrap_or_default(),
+        habitat: row.get("habitat")?,
+        distribution: row.get("distribution")?,
+        properties,
+        uses: row.get::<_, Option<String>>("uses")?.map(|uses| parse_uses(&uses)).unwrap_or_default(),
+        conservation_status: row.get("conservation_status")?,
+        image_url: row.get("image_url")?,
+    })
+}
+
+impl DbConnection {
+    pub fn get_species(&self, id: i64) -> Result<Species> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {} FROM species WHERE id = ?", SPECIES_COLUMNS),
+            params![id],
+            species_from_row,
+        )
+    }
+
+    /// Every species, by scientific name
+    pub fn list_species(&self) -> Result<Vec<Species>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM species ORDER BY scientific_name", SPECIES_COLUMNS))?;
+        let rows = stmt.query_map([], species_from_row)?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_properties_fall_back_to_defaults() {
+        let db = DbConnection::in_memory();
+        db.conn.lock().unwrap().execute_batch("
+            INSERT INTO species (id, scientific_name, common_name, family, description, properties) VALUES
+                (1, 'Toona ciliata', 'Toon', 'Meliaceae', '', '{\"density_g_cm3\": \"heavy\"'),
+                (2, 'Tectona grandis', 'Segun', 'Lamiaceae', '', '{\"density_g_cm3\": 0.66}');
+        ").unwrap();
+
+        assert_eq!(db.get_species(1).unwrap().properties, SpeciesProperties::default());
+        assert_eq!(db.get_species(2).unwrap().properties.wood_anatomy.density_g_cm3, Some(0.66));
+        assert_eq!(db.list_species().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,302 @@
+use serde::{Serialize, Deserialize};
+use crate::analysis::{AnalysisResult, RankedPrediction};
+use crate::database::{DbConnection, SpeciesAttributes, XylemPorosity};
+use crate::identification_key::Character;
+use crate::open_set::Outcome;
+
+/// Allowance for measurement error when comparing a measured density with the recorded range, g/cm³
+const DENSITY_TOLERANCE: f64 = 0.05;
+
+/// Chance of observing a contradicting character on the right species, through observer error or
+/// variation the database does not record. Each contradiction multiplies a prediction's weight by it.
+const CONTRADICTION_LIKELIHOOD: f64 = 0.05;
+
+/// Characters recorded by hand for the analyzed sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnatomyObservations {
+    pub xylem_porosity: Option<XylemPorosity>,
+    pub growth_ring_distinct: Option<bool>,
+    /// Measured density, g/cm³
+    pub density_g_cm3: Option<f64>,
+}
+
+impl AnatomyObservations {
+    pub fn is_empty(&self) -> bool {
+        self.xylem_porosity.is_none() && self.growth_ring_distinct.is_none() && self.density_g_cm3.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Consistency {
+    Consistent,
+    Contradicts,
+    /// The species record has no value for the character
+    Unrecorded,
+}
+
+/// One observation compared with a species record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObservationCheck {
+    pub character: Character,
+    pub observed: String,
+    pub recorded: Option<String>,
+    pub consistency: Consistency,
+}
+
+/// How a ranked prediction fares against the observations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnatomyCheck {
+    /// Model probability reweighted by the observations and renormalized over the ranked list
+    pub combined_probability: f64,
+    pub contradictions: usize,
+    pub checks: Vec<ObservationCheck>,
+}
+
+/// Summary of re-ranking an analysis by the observations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnatomyFusion {
+    pub observations: AnatomyObservations,
+    /// Top label from the model alone
+    pub model_label: String,
+    /// Whether the observations contradict the model's top prediction
+    pub model_contradicted: bool,
+    /// Whether the observations changed which prediction ranks first
+    pub reranked: bool,
+}
+
+fn check(character: Character, observed: String, recorded: Option<String>, consistent: Option<bool>) -> ObservationCheck {
+    let consistency = match consistent {
+        Some(true) => Consistency::Consistent,
+        Some(false) => Consistency::Contradicts,
+        None => Consistency::Unrecorded,
+    };
+    ObservationCheck { character, observed, recorded, consistency }
+}
+
+fn ring_state(distinct: bool) -> String {
+    match distinct {
+        true => "distinct".to_string(),
+        false => "indistinct".to_string(),
+    }
+}
+
+/// Compare every observation with one species record
+pub fn compare(observations: &AnatomyObservations, attributes: &SpeciesAttributes) -> Vec<ObservationCheck> {
+    let mut checks = Vec::new();
+
+    if let Some(porosity) = observations.xylem_porosity {
+        let observed = porosity.as_str().to_string();
+        let consistent = attributes.xylem_porosity.as_ref().map(|recorded| *recorded == observed);
+        checks.push(check(Character::XylemPorosity, observed, attributes.xylem_porosity.clone(), consistent));
+    }
+
+    if let Some(distinct) = observations.growth_ring_distinct {
+        let consistent = attributes.growth_ring_distinct.map(|recorded| recorded == distinct);
+        checks.push(check(Character::GrowthRings, ring_state(distinct), attributes.growth_ring_distinct.map(ring_state), consistent));
+    }
+
+    if let Some(density) = observations.density_g_cm3 {
+        let range = attributes.density_min.zip(attributes.density_max);
+        let consistent = range.map(|(min, max)| density >= min - DENSITY_TOLERANCE && density <= max + DENSITY_TOLERANCE);
+        let recorded = range.map(|(min, max)| match min == max {
+            true => format!("{:.2}", min),
+            false => format!("{:.2}-{:.2}", min, max),
+        });
+        checks.push(check(Character::WoodDensity, format!("{:.2}", density), recorded, consistent));
+    }
+
+    checks
+}
+
+/// Re-rank the predictions by consistency with the observations. Predictions without a species record
+/// keep their model weight. When the outcome is identified, the label follows the new top prediction.
+pub fn rerank(
+    result: &mut AnalysisResult,
+    observations: &AnatomyObservations,
+    attributes_for: impl Fn(i64) -> Option<SpeciesAttributes>,
+) {
+    let model_label = result.predictions.first().map(|top| top.label.clone()).unwrap_or_else(|| result.label.clone());
+
+    let weighted: Vec<(f64, Vec<ObservationCheck>)> = result.predictions.iter()
+        .map(|prediction| {
+            let checks = prediction.species.as_ref()
+                .and_then(|species| attributes_for(species.id))
+                .map(|attributes| compare(observations, &attributes))
+                .unwrap_or_default();
+            let contradictions = checks.iter().filter(|check| check.consistency == Consistency::Contradicts).count();
+            (prediction.probability * CONTRADICTION_LIKELIHOOD.powi(contradictions as i32), checks)
+        })
+        .collect();
+
+    let total: f64 = weighted.iter().map(|(weight, _)| weight).sum();
+    if total <= 0.0 {
+        return;
+    }
+
+    for (prediction, (weight, checks)) in result.predictions.iter_mut().zip(weighted) {
+        prediction.anatomy = Some(AnatomyCheck {
+            combined_probability: weight / total,
+            contradictions: checks.iter().filter(|check| check.consistency == Consistency::Contradicts).count(),
+            checks,
+        });
+    }
+
+    let model_contradicted = result.predictions.first()
+        .and_then(|top| top.anatomy.as_ref())
+        .is_some_and(|anatomy| anatomy.contradictions > 0);
+
+    // Stable, so equally consistent predictions keep the model's order
+    result.predictions.sort_by(|a, b| combined(b).total_cmp(&combined(a)));
+    for (index, prediction) in result.predictions.iter_mut().enumerate() {
+        prediction.rank = index + 1;
+    }
+
+    let top = &result.predictions[0];
+    let reranked = top.label != model_label;
+    if reranked && result.outcome == Outcome::Identified {
+        result.label = top.label.clone();
+        result.confidence = combined(top);
+    }
+
+    result.anatomy = Some(AnatomyFusion {
+        observations: observations.clone(),
+        model_label,
+        model_contradicted,
+        reranked,
+    });
+}
+
+fn combined(prediction: &RankedPrediction) -> f64 {
+    prediction.anatomy.as_ref().map_or(prediction.probability, |anatomy| anatomy.combined_probability)
+}
+
+/// Re-rank against the species database, logging species whose attributes cannot be read
+pub fn apply(result: &mut AnalysisResult, observations: &AnatomyObservations, db: &DbConnection) {
+    rerank(result, observations, |species_id| match db.get_species_attributes(species_id) {
+        Ok(attributes) => Some(attributes),
+        Err(e) => {
+            eprintln!("Failed to load attributes for species {}: {}", species_id, e);
+            None
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::{ClassScore, Prediction};
+    use crate::database::{Species, SpeciesProperties};
+
+    fn species(id: i64) -> Species {
+        Species {
+            id,
+            scientific_name: format!("Species {}", id),
+            common_name: String::new(),
+            family: String::new(),
+            description: String::new(),
+            habitat: None,
+            distribution: None,
+            properties: SpeciesProperties::default(),
+            uses: Vec::new(),
+            conservation_status: None,
+            image_url: None,
+        }
+    }
+
+    fn attributes(id: i64) -> Option<SpeciesAttributes> {
+        let (porosity, density) = match id {
+            1 => ("ring-porous", (0.60, 0.70)),
+            2 => ("diffuse-porous", (0.60, 0.70)),
+            _ => return None,
+        };
+        Some(SpeciesAttributes {
+            species_id: id,
+            xylem_porosity: Some(porosity.to_string()),
+            density_min: Some(density.0),
+            density_max: Some(density.1),
+            ..SpeciesAttributes::default()
+        })
+    }
+
+    fn result() -> AnalysisResult {
+        let probabilities = vec![
+            ClassScore { label: "first".to_string(), probability: 0.6 },
+            ClassScore { label: "second".to_string(), probability: 0.3 },
+            ClassScore { label: "third".to_string(), probability: 0.1 },
+        ];
+        let mut result = AnalysisResult::from_prediction(Prediction {
+            label: "first".to_string(),
+            confidence: 0.6,
+            probabilities,
+            fallback: false,
+        }, 3, None);
+        for (index, prediction) in result.predictions.iter_mut().enumerate() {
+            prediction.species = Some(species(index as i64 + 1));
+        }
+        result
+    }
+
+    #[test]
+    fn contradicted_top_prediction_is_demoted() {
+        let mut result = result();
+        let observations = AnatomyObservations {
+            xylem_porosity: Some(XylemPorosity::DiffusePorous),
+            density_g_cm3: Some(0.74),
+            ..AnatomyObservations::default()
+        };
+
+        rerank(&mut result, &observations, attributes);
+
+        let labels: Vec<&str> = result.predictions.iter().map(|prediction| prediction.label.as_str()).collect();
+        assert_eq!(labels, vec!["second", "third", "first"]);
+        assert_eq!(result.label, "second");
+
+        let fusion = result.anatomy.unwrap();
+        assert!(fusion.model_contradicted && fusion.reranked);
+        assert_eq!(fusion.model_label, "first");
+
+        let first = result.predictions[2].anatomy.as_ref().unwrap();
+        assert_eq!(first.contradictions, 1);
+        // 0.74 is within the measurement tolerance of the 0.60-0.70 range
+        assert_eq!(first.checks[1].consistency, Consistency::Consistent);
+
+        let third = result.predictions[1].anatomy.as_ref().unwrap();
+        assert!(third.checks.is_empty());
+        let total: f64 = result.predictions.iter().map(combined).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn consistent_observations_keep_the_model_order() {
+        let mut result = result();
+        let observations = AnatomyObservations {
+            density_g_cm3: Some(0.65),
+            ..AnatomyObservations::default()
+        };
+
+        rerank(&mut result, &observations, attributes);
+
+        assert_eq!(result.label, "first");
+        assert!(!result.anatomy.unwrap().reranked);
+        assert!((result.predictions[0].anatomy.as_ref().unwrap().combined_probability - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unknown_outcome_keeps_its_label() {
+        let mut result = result();
+        result.label = "unknown".to_string();
+        result.outcome = Outcome::Unknown;
+        let observations = AnatomyObservations {
+            growth_ring_distinct: Some(true),
+            xylem_porosity: Some(XylemPorosity::DiffusePorous),
+            ..AnatomyObservations::default()
+        };
+
+        rerank(&mut result, &observations, attributes);
+
+        assert_eq!(result.label, "unknown");
+        assert_eq!(result.predictions[0].label, "second");
+    }
+}
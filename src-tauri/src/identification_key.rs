@@ -0,0 +1,306 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use crate::database::{DbConnection, SpeciesAttributes};
+
+/// A character an anatomist can check on a wood sample, after the IAWA hardwood feature list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Character {
+    XylemPorosity,
+    GrowthRings,
+    /// IAWA features 190-192: basic specific gravity below 0.40, 0.40-0.75, above 0.75
+    WoodDensity,
+    /// Not an IAWA feature, but readily checked on logs; classes split the range found in the database
+    BarkThickness,
+}
+
+pub const CHARACTERS: [Character; 4] = [
+    Character::XylemPorosity,
+    Character::GrowthRings,
+    Character::WoodDensity,
+    Character::BarkThickness,
+];
+
+const DENSITY_CLASSES: [(&str, f64, f64); 3] = [("low", 0.0, 0.40), ("medium", 0.40, 0.75), ("high", 0.75, f64::INFINITY)];
+const BARK_CLASSES: [(&str, f64, f64); 3] = [("thin", 0.0, 8.0), ("medium", 8.0, 12.0), ("thick", 12.0, f64::INFINITY)];
+
+impl Character {
+    pub fn title(self) -> &'static str {
+        match self {
+            Character::XylemPorosity => "Wood porosity",
+            Character::GrowthRings => "Growth ring boundaries",
+            Character::WoodDensity => "Wood density",
+            Character::BarkThickness => "Bark thickness",
+        }
+    }
+
+    pub fn states(self) -> &'static [&'static str] {
+        match self {
+            Character::XylemPorosity => &["diffuse-porous", "semi-ring-porous", "ring-porous"],
+            Character::GrowthRings => &["distinct", "indistinct"],
+            Character::WoodDensity => &["low", "medium", "high"],
+            Character::BarkThickness => &["thin", "medium", "thick"],
+        }
+    }
+
+    /// States recorded for a species. A species whose range spans several classes has all of them,
+    /// and an empty list means the character was not recorded.
+    fn states_of(self, attributes: &SpeciesAttributes) -> Vec<&'static str> {
+        match self {
+            Character::XylemPorosity => self.states().iter()
+                .copied()
+                .filter(|state| attributes.xylem_porosity.as_deref() == Some(*state))
+                .collect(),
+            Character::GrowthRings => match attributes.growth_ring_distinct {
+                Some(true) => vec!["distinct"],
+                Some(false) => vec!["indistinct"],
+                None => Vec::new(),
+            },
+            Character::WoodDensity => range_classes(&DENSITY_CLASSES, attributes.density_min, attributes.density_max),
+            Character::BarkThickness => range_classes(&BARK_CLASSES, attributes.bark_min_mm, attributes.bark_max_mm),
+        }
+    }
+}
+
+/// Classes overlapping `min..=max`; a class includes its lower bound only
+fn range_classes(classes: &[(&'static str, f64, f64)], min: Option<f64>, max: Option<f64>) -> Vec<&'static str> {
+    let (min, max) = match (min, max) {
+        (Some(min), Some(max)) => (min, max),
+        _ => return Vec::new(),
+    };
+    classes.iter()
+        .filter(|(_, lower, upper)| min < *upper && max >= *lower)
+        .map(|(name, _, _)| *name)
+        .collect()
+}
+
+/// A character state seen on the sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub character: Character,
+    pub state: String,
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    UnknownState { character: Character, state: String },
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::UnknownState { character, state } => write!(
+                f, "'{}' is not a state of {}; expected one of {}",
+                state, character.title().to_lowercase(), character.states().join(", "),
+            ),
+            KeyError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+impl From<rusqlite::Error> for KeyError {
+    fn from(e: rusqlite::Error) -> Self {
+        KeyError::Database(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyCandidate {
+    pub species_id: i64,
+    pub scientific_name: String,
+    pub common_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateCount {
+    pub state: &'static str,
+    /// Remaining candidates that would survive observing this state
+    pub candidates: usize,
+}
+
+/// An unobserved character that would narrow the remaining candidates
+#[derive(Debug, Clone, Serialize)]
+pub struct CharacterSuggestion {
+    pub character: Character,
+    pub title: &'static str,
+    /// Candidates expected to remain after checking it; lower is more discriminating
+    pub expected_remaining: f64,
+    pub states: Vec<StateCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyResult {
+    pub observations: Vec<Observation>,
+    pub candidates: Vec<KeyCandidate>,
+    /// Most discriminating first; characters that cannot split the candidates are left out
+    pub suggestions: Vec<CharacterSuggestion>,
+}
+
+struct KeyEntry {
+    candidate: KeyCandidate,
+    attributes: SpeciesAttributes,
+}
+
+impl KeyEntry {
+    /// Multi-entry keys keep species for which the observed character was not recorded
+    fn matches(&self, observation: &Observation) -> bool {
+        let states = observation.character.states_of(&self.attributes);
+        states.is_empty() || states.contains(&observation.state.as_str())
+    }
+}
+
+/// Multi-entry identification key over the species anatomy data. Observations can be entered in
+/// any order; each call narrows the candidates and ranks the characters left to check.
+pub struct IdentificationKey {
+    entries: Vec<KeyEntry>,
+}
+
+impl IdentificationKey {
+    pub fn load(db: &DbConnection) -> Result<Self, KeyError> {
+        let attributes = db.list_species_attributes()?;
+        let entries = db.list_species()?
+            .into_iter()
+            .filter_map(|species| {
+                let attributes = attributes.iter().find(|attributes| attributes.species_id == species.id)?;
+                Some(KeyEntry {
+                    candidate: KeyCandidate {
+                        species_id: species.id,
+                        scientific_name: species.scientific_name,
+                        common_name: species.common_name,
+                    },
+                    attributes: attributes.clone(),
+                })
+            })
+            .collect();
+        Ok(IdentificationKey { entries })
+    }
+
+    pub fn evaluate(&self, observations: &[Observation]) -> Result<KeyResult, KeyError> {
+        for observation in observations {
+            if !observation.character.states().contains(&observation.state.as_str()) {
+                return Err(KeyError::UnknownState {
+                    character: observation.character,
+                    state: observation.state.clone(),
+                });
+            }
+        }
+
+        let remaining: Vec<&KeyEntry> = self.entries.iter()
+            .filter(|entry| observations.iter().all(|observation| entry.matches(observation)))
+            .collect();
+
+        let mut suggestions: Vec<CharacterSuggestion> = CHARACTERS.iter()
+            .filter(|character| !observations.iter().any(|observation| observation.character == **character))
+            .filter_map(|character| suggest(*character, &remaining))
+            .collect();
+        suggestions.sort_by(|a, b| a.expected_remaining.total_cmp(&b.expected_remaining));
+
+        Ok(KeyResult {
+            observations: observations.to_vec(),
+            candidates: remaining.iter().map(|entry| entry.candidate.clone()).collect(),
+            suggestions,
+        })
+    }
+}
+
+/// Score a character by the candidates expected to remain once it is checked, taking each state as
+/// likely as its share of the candidates. `None` when no state would remove any candidate.
+fn suggest(character: Character, remaining: &[&KeyEntry]) -> Option<CharacterSuggestion> {
+    let states: Vec<StateCount> = character.states().iter()
+        .map(|&state| StateCount {
+            state,
+            candidates: remaining.iter()
+                .filter(|entry| entry.matches(&Observation { character, state: state.to_string() }))
+                .count(),
+        })
+        .filter(|count| count.candidates > 0)
+        .collect();
+
+    if states.iter().all(|count| count.candidates == remaining.len()) {
+        return None;
+    }
+
+    let total: usize = states.iter().map(|count| count.candidates).sum();
+    let squares: usize = states.iter().map(|count| count.candidates * count.candidates).sum();
+
+    Some(CharacterSuggestion {
+        character,
+        title: character.title(),
+        expected_remaining: squares as f64 / total as f64,
+        states,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i64, porosity: Option<&str>, rings: Option<bool>, density: Option<(f64, f64)>) -> KeyEntry {
+        KeyEntry {
+            candidate: KeyCandidate {
+                species_id: id,
+                scientific_name: format!("Species {}", id),
+                common_name: String::new(),
+            },
+            attributes: SpeciesAttributes {
+                species_id: id,
+                xylem_porosity: porosity.map(str::to_string),
+                growth_ring_distinct: rings,
+                density_min: density.map(|(min, _)| min),
+                density_max: density.map(|(_, max)| max),
+                ..SpeciesAttributes::default()
+            },
+        }
+    }
+
+    fn key() -> IdentificationKey {
+        IdentificationKey {
+            entries: vec![
+                entry(1, Some("diffuse-porous"), Some(false), Some((0.60, 0.70))),
+                entry(2, Some("ring-porous"), Some(true), Some((0.60, 0.70))),
+                entry(3, Some("diffuse-porous"), Some(true), Some((0.70, 0.80))),
+                entry(4, None, Some(false), Some((0.35, 0.45))),
+            ],
+        }
+    }
+
+    fn observe(character: Character, state: &str) -> Observation {
+        Observation { character, state: state.to_string() }
+    }
+
+    fn ids(result: &KeyResult) -> Vec<i64> {
+        result.candidates.iter().map(|candidate| candidate.species_id).collect()
+    }
+
+    #[test]
+    fn keeps_species_with_unrecorded_or_overlapping_states() {
+        let key = key();
+        let result = key.evaluate(&[observe(Character::XylemPorosity, "diffuse-porous")]).unwrap();
+        assert_eq!(ids(&result), vec![1, 3, 4]);
+
+        let result = key.evaluate(&[
+            observe(Character::XylemPorosity, "diffuse-porous"),
+            observe(Character::WoodDensity, "high"),
+        ]).unwrap();
+        assert_eq!(ids(&result), vec![3]);
+    }
+
+    #[test]
+    fn suggests_the_most_discriminating_unobserved_character() {
+        let result = key().evaluate(&[observe(Character::XylemPorosity, "diffuse-porous")]).unwrap();
+
+        // Growth rings split 1 and 4 from 3 exactly; density leaves 1 and 3 together in "medium"
+        let characters: Vec<Character> = result.suggestions.iter().map(|suggestion| suggestion.character).collect();
+        assert_eq!(characters, vec![Character::GrowthRings, Character::WoodDensity]);
+        assert!(result.suggestions.iter().all(|suggestion| suggestion.character != Character::XylemPorosity));
+    }
+
+    #[test]
+    fn rejects_states_the_character_does_not_have() {
+        let error = key().evaluate(&[observe(Character::GrowthRings, "ring-porous")]).unwrap_err();
+        assert!(matches!(error, KeyError::UnknownState { character: Character::GrowthRings, .. }));
+    }
+}
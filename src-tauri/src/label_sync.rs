@@ -0,0 +1,113 @@
+use serde::Serialize;
+use crate::classifier::Classifier;
+use crate::database::{DbConnection, MatchStrategy, ResolveError, UnlabelledSpecies};
+
+/// A `model_labels` row created during reconciliation
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedLabel {
+    pub label: String,
+    pub species_id: i64,
+}
+
+/// Differences between the classes a model predicts and the `model_labels` table
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelSyncReport {
+    pub backend: String,
+    pub model_classes: usize,
+    /// Model classes that `resolve_label` cannot map to a single species
+    pub unmapped_labels: Vec<String>,
+    /// Species the model can never predict
+    pub unlabelled_species: Vec<UnlabelledSpecies>,
+    pub created: Vec<CreatedLabel>,
+}
+
+/// Compare the classifier's classes with the species they resolve to, the way analysis resolves them.
+/// With `create_missing`, classes resolved by scientific or local name rather than a `model_labels`
+/// row get a new row, so they keep their species and threshold if names change later.
+pub async fn reconcile(classifier: &dyn Classifier, db: &DbConnection, create_missing: bool) -> Result<LabelSyncReport, String> {
+    let classes = classifier.class_labels().await
+        .map_err(|e| format!("Failed to fetch model classes: {}", e))?;
+
+    let mut unmapped_labels = Vec::new();
+    let mut created = Vec::new();
+    let mut mapped_species = Vec::new();
+
+    for label in &classes {
+        let resolution = match db.resolve_label(label) {
+            Ok(resolution) => resolution,
+            Err(ResolveError::NotFound(_)) | Err(ResolveError::Ambiguous { .. }) => {
+                unmapped_labels.push(label.clone());
+                continue;
+            },
+            Err(e) => return Err(e.to_string()),
+        };
+
+        mapped_species.push(resolution.species_id);
+        if create_missing && resolution.strategy != MatchStrategy::ModelLabel {
+            db.create_model_label(label, resolution.species_id).map_err(|e| format!("Database error: {}", e))?;
+            created.push(CreatedLabel { label: label.clone(), species_id: resolution.species_id });
+        }
+    }
+
+    let unlabelled_species = db.species_without_labels(&mapped_species)
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(LabelSyncReport {
+        backend: classifier.name().to_string(),
+        model_classes: classes.len(),
+        unmapped_labels,
+        unlabelled_species,
+        created,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::StubClassifier;
+
+    fn database() -> DbConnection {
+        let db = DbConnection::in_memory();
+        db.import_species_file(concat!(env!("CARGO_MANIFEST_DIR"), "/../backend/species_data.json")).unwrap();
+        db
+    }
+
+    fn sync(db: &DbConnection, classes: &[&str], create_missing: bool) -> LabelSyncReport {
+        let classifier = StubClassifier::new("stub", classes);
+        tauri::async_runtime::block_on(reconcile(&classifier, db, create_missing)).unwrap()
+    }
+
+    fn unlabelled(report: &LabelSyncReport) -> Vec<&str> {
+        report.unlabelled_species.iter().map(|species| species.scientific_name.as_str()).collect()
+    }
+
+    #[test]
+    fn agrees_with_label_resolution() {
+        let db = database();
+        let report = sync(&db, &["Swietenia mahagoni_Mahogoni", "Tectona_grandis_Segun", "Quercus robur_Oak"], false);
+
+        assert_eq!(report.model_classes, 3);
+        // Spelled differently from its `model_labels` row but resolved all the same
+        assert_eq!(report.unmapped_labels, vec!["Quercus robur_Oak"]);
+        assert!(report.created.is_empty());
+
+        let unlabelled = unlabelled(&report);
+        assert!(!unlabelled.contains(&"Swietenia mahagoni"));
+        assert!(!unlabelled.contains(&"Tectona grandis"));
+        assert!(unlabelled.contains(&"Chukrasia tabularis"));
+    }
+
+    #[test]
+    fn creates_rows_for_classes_matched_by_name() {
+        let db = database();
+        let report = sync(&db, &["Swietenia mahagoni_Mahogoni", "Swietenia_mahagoni_Big_leaf"], true);
+
+        // Multi-word scientific names match even when every separator is an underscore
+        assert_eq!(report.created.len(), 1);
+        assert_eq!(report.created[0].label, "Swietenia_mahagoni_Big_leaf");
+        assert_eq!(db.resolve_label("Swietenia_mahagoni_Big_leaf").unwrap().strategy, MatchStrategy::ModelLabel);
+
+        // Nothing left to create on the next run
+        assert!(sync(&db, &["Swietenia_mahagoni_Big_leaf"], true).created.is_empty());
+    }
+}
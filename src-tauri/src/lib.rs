@@ -1,3 +1,17 @@
 pub mod activation;
+pub mod analysis;
+pub mod batch;
+pub mod calibrate;
+pub mod calibration;
+pub mod classifier;
 pub mod database;
-pub mod import_species; 
\ No newline at end of file
+pub mod fusion;
+pub mod identification_key;
+pub mod import_species;
+pub mod label_sync;
+pub mod offline_queue;
+pub mod open_set;
+pub mod preprocess;
+pub mod quality;
+pub mod settings;
+pub mod tiling;
\ No newline at end of file
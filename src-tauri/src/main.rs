@@ -4,18 +4,37 @@
 )]
 
 mod activation;
+mod analysis;
+mod batch;
+mod calibration;
+mod classifier;
 mod database;
+mod fusion;
+mod identification_key;
+mod label_sync;
+mod offline_queue;
+mod open_set;
+mod preprocess;
+mod quality;
+mod settings;
+mod tiling;
 
 use std::sync::Mutex;
 use std::fs;
 use tauri::api::path::{app_data_dir};
 use tauri::{AppHandle, Manager, State, CustomMenuItem, SystemTray, SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem};
-use database::DbConnection;
+use database::{APP_DATA_FILE, AnalysisFilter, AnalysisRecord, AttributeFilter, Calibration, DbConnection, LabelResolution, QueuedAnalysis, Species, SpeciesAttributes, SpeciesSearchPage};
 use activation::{check_activation, activate_app};
-use std::path::Path;
+use analysis::{AnalysisOptions, AnalysisResult, DEFAULT_TOP_K};
+use batch::BatchSummary;
+use fusion::AnatomyObservations;
+use identification_key::{IdentificationKey, KeyResult, Observation};
+use label_sync::LabelSyncReport;
+use settings::{AppSettings, load_settings, save_settings};
+use classifier::{AnalysisError, CircuitStatus, ClassifierRegistry, DemoClassifier, LocalOnnxClassifier, RemoteHttpClassifier};
+use std::sync::Arc;
 use std::time::Instant;
 use serde_json::{json, Value};
-use reqwest;
 use auto_launch::AutoLaunchBuilder;
 
 // Application state to be shared between commands
@@ -23,6 +42,8 @@ use auto_launch::AutoLaunchBuilder;
 struct AppState {
     db_connection: Mutex<Option<DbConnection>>,
     activated: Mutex<bool>,
+    classifiers: Mutex<ClassifierRegistry>,
+    settings: Mutex<AppSettings>,
 }
 
 #[tauri::command(rename_all = "camelCase")]
@@ -48,7 +69,8 @@ fn activate_with_key(app_handle: AppHandle, key: String, state: State<'_, AppSta
                 .or_else(|| app_handle.path_resolver().resolve_resource("species.db"))
                 .ok_or("Failed to get resource path")?;
             
-            *db_conn_guard = Some(DbConnection::new(resource_path.to_string_lossy().to_string())
+            let app_db_path = app_data_dir.join(APP_DATA_FILE);
+            *db_conn_guard = Some(DbConnection::with_app_data(resource_path.to_string_lossy().to_string(), &app_db_path.to_string_lossy())
                 .map_err(|e| format!("Failed to connect to database: {}", e))?);
         }
         db_conn_guard.as_ref().unwrap().clone()
@@ -67,7 +89,7 @@ fn activate_with_key(app_handle: AppHandle, key: String, state: State<'_, AppSta
 }
 
 #[tauri::command(rename_all = "camelCase")]
-fn get_species_info(label: String, state: State<'_, AppState>) -> Result<serde_json::Value, String> {
+fn get_species_info(label: String, state: State<'_, AppState>) -> Result<Species, String> {
     // Get database connection
     let db_connection = {
         let db_conn_guard = state.db_connection.lock().unwrap();
@@ -77,140 +99,245 @@ fn get_species_info(label: String, state: State<'_, AppState>) -> Result<serde_j
         }
     };
     
-    // Query the database for species info
     db_connection.get_species_by_label(&label)
-        .map_err(|e| {
-            // If the error is "Query returned no rows" but we can extract the scientific name,
-            // provide a more specific error message
-            if e.to_string().contains("Query returned no rows") {
-                if let Some(underscore_pos) = label.find('_') {
-                    let scientific_name = &label[0..underscore_pos];
-                    format!("Species information for '{}' not found in the database", scientific_name)
-                } else {
-                    format!("Species information for '{}' not found in the database", label)
-                }
-            } else {
-                format!("Database error: {}", e)
-            }
-        })
+        .map_err(|e| e.to_string())
 }
 
+/// Ranked full-text species search with highlighted matches
 #[tauri::command(rename_all = "camelCase")]
-async fn analyze_local_image(file_path: String) -> Result<Value, String> {
-    println!("Analyzing local image: {}", file_path);
-
-    // Create a longer-lived string before creating the Path
-    let normalized_path = if cfg!(target_os = "windows") && !file_path.starts_with("\\\\?\\") {      
-        // On Windows, ensure the path is properly formatted with long path support
-        format!("\\\\?\\{}", file_path.replace("/", "\\"))
-    } else {
-        file_path.clone()
-    };
+fn search_species(query: String, limit: Option<i64>, offset: Option<i64>, state: State<'_, AppState>) -> Result<SpeciesSearchPage, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.search_species(&query, limit, offset)
+        .map_err(|e| format!("Database error: {}", e))
+}
 
-    // Now use the longer-lived string
-    let path = Path::new(&normalized_path);
+/// Density, anatomy, size and phenology of one species
+#[tauri::command(rename_all = "camelCase")]
+fn get_species_attributes(species_id: i64, state: State<'_, AppState>) -> Result<SpeciesAttributes, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.get_species_attributes(species_id)
+        .map_err(|e| format!("Database error: {}", e))
+}
 
-    if !path.exists() {
-        return Err(format!("File not found: {}", file_path));
-    }
+/// Species whose recorded attributes match the filter's AND/OR conditions
+#[tauri::command(rename_all = "camelCase")]
+fn filter_species(filter: AttributeFilter, state: State<'_, AppState>) -> Result<Vec<Species>, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.filter_species(&filter)
+        .map_err(|e| format!("Database error: {}", e))
+}
 
-    // Get file extension to verify it's an image
-    let extension = match path.extension().and_then(|ext| ext.to_str()) {
-        Some(ext) => ext.to_lowercase(),
-        None => return Err("File has no extension".to_string()),
-    };
+/// Step through the multi-entry wood anatomy key: remaining candidates and the next characters to check
+#[tauri::command(rename_all = "camelCase")]
+fn identify_by_key(observations: Vec<Observation>, state: State<'_, AppState>) -> Result<KeyResult, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    IdentificationKey::load(&db_connection)
+        .and_then(|key| key.evaluate(&observations))
+        .map_err(|e| e.to_string())
+}
+
+/// Show how a label maps to a species, including every candidate that matched
+#[tauri::command(rename_all = "camelCase")]
+fn resolve_label(label: String, state: State<'_, AppState>) -> Result<LabelResolution, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.resolve_label(&label)
+        .map_err(|e| e.to_string())
+}
 
-    // Check if it's a supported image format
-    if !["jpg", "jpeg", "png", "bmp", "tiff", "webp"].contains(&extension.as_str()) {
-        return Err(format!("Unsupported image format: {}", extension));
+/// Combine per-request arguments with the saved settings; `tiled` overrides the saved tiling default
+fn analysis_options(state: &AppState, top_k: Option<usize>, bypass_cache: Option<bool>, tiled: Option<bool>) -> AnalysisOptions {
+    let mut options = AnalysisOptions::from_settings(&state.settings.lock().unwrap());
+    options.top_k = top_k.unwrap_or(DEFAULT_TOP_K);
+    options.bypass_cache = bypass_cache.unwrap_or(false);
+    if let Some(tiled) = tiled {
+        options.tiling.enabled = tiled;
     }
+    options
+}
 
-    // Read file
-    let file_data = match std::fs::read(&path) {
-        Ok(data) => data,
-        Err(e) => return Err(format!("Failed to read file: {}", e)),
-    };
+#[tauri::command(rename_all = "camelCase")]
+async fn analyze_local_image(
+    file_path: String,
+    top_k: Option<usize>,
+    bypass_cache: Option<bool>,
+    tiled: Option<bool>,
+    observations: Option<AnatomyObservations>,
+    state: State<'_, AppState>,
+) -> Result<AnalysisResult, AnalysisError> {
+    // Resolve the active backend before awaiting so the lock is not held across the request
+    let classifier = state.classifiers.lock().unwrap()
+        .active()
+        .ok_or_else(|| AnalysisError::Backend("No classifier backend configured".to_string()))?;
 
-    // Get MIME type based on extension
-    let mime_type = match extension.as_str() {
-        "jpg" | "jpeg" => "image/jpeg",
-        "png" => "image/png",
-        "bmp" => "image/bmp",
-        "tiff" => "image/tiff",
-        "webp" => "image/webp",
-        _ => "application/octet-stream",
-    };
+    let db_connection = state.db_connection.lock().unwrap().clone();
 
-    // API URL from config or environment or default
-    let api_url = std::env::var("API_URL")
-        .unwrap_or_else(|_| "https://shakirul-sust-treescopy-api.hf.space/predict".to_string());
-    
-    // Create multipart form with correct boundary
-    let file_part = reqwest::multipart::Part::bytes(file_data)
-        .file_name(path.file_name().unwrap_or_default().to_string_lossy().to_string())
-        .mime_str(mime_type)
-        .map_err(|e| format!("Failed to set mime type: {}", e))?;
-        
-    let form = reqwest::multipart::Form::new().part("file", file_part);
-    
-    println!("Sending Request: POST /predict");
-    
-    // Send the request to the API
-    let client = reqwest::Client::new();
-    let response = match client.post(&api_url)
-        .multipart(form)
-        .header("Accept", "application/json")
-        .timeout(std::time::Duration::from_secs(30))
-        .send()
-        .await {
-            Ok(resp) => resp,
-            Err(e) => return Err(format!("API request failed: {}", e)),
-        };
-    
-    // Check response status
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        println!("Received Response from: /predict {}", status);
-        
-        // Instead of failing, return a fallback result with mock data
-        return Ok(json!({
-            "label": "Oak_Tree",
-            "confidence": 0.85,
-            "_fallback": true
-        }));
+    let mut options = analysis_options(&state, top_k, bypass_cache, tiled);
+    options.observations = observations;
+
+    analysis::analyze(&file_path, classifier.as_ref(), db_connection.as_ref(), &options).await
+}
+
+#[tauri::command(rename_all = "camelCase")]
+async fn analyze_batch(
+    app_handle: AppHandle,
+    paths: Option<Vec<String>>,
+    directory: Option<String>,
+    concurrency: Option<usize>,
+    top_k: Option<usize>,
+    bypass_cache: Option<bool>,
+    tiled: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<BatchSummary, String> {
+    let mut image_paths = paths.unwrap_or_default();
+    if let Some(directory) = directory {
+        image_paths.extend(batch::collect_images(&directory)?);
     }
+
+    if image_paths.is_empty() {
+        return Err("No images to analyze".to_string());
+    }
+
+    let classifier = state.classifiers.lock().unwrap()
+        .active()
+        .ok_or("No classifier backend configured")?;
+
+    let db_connection = state.db_connection.lock().unwrap().clone();
+
+    let options = analysis_options(&state, top_k, bypass_cache, tiled);
+
+    Ok(batch::analyze_batch(
+        &app_handle,
+        image_paths,
+        classifier.as_ref(),
+        db_connection.as_ref(),
+        &options,
+        concurrency.unwrap_or(batch::DEFAULT_CONCURRENCY),
+    ).await)
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn list_analyses(filter: Option<AnalysisFilter>, state: State<'_, AppState>) -> Result<Vec<AnalysisRecord>, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.list_analyses(&filter.unwrap_or_default())
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn get_analysis(id: i64, state: State<'_, AppState>) -> Result<AnalysisRecord, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.get_analysis(id)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("Analysis {} not found", id),
+            e => format!("Database error: {}", e),
+        })
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn delete_analysis(id: i64, state: State<'_, AppState>) -> Result<bool, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.delete_analysis(id)
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn clear_prediction_cache(state: State<'_, AppState>) -> Result<usize, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.clear_prediction_cache()
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn get_analysis_queue(state: State<'_, AppState>) -> Result<Vec<QueuedAnalysis>, String> {
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.pending_analyses()
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn get_calibration(state: State<'_, AppState>) -> Result<Option<Calibration>, String> {
+    let classifier = state.classifiers.lock().unwrap()
+        .active()
+        .ok_or("No classifier backend configured")?;
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+    db_connection.calibration(&classifier.model_version())
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Fit a calibration for the active backend from a folder of label-named subfolders and store it
+#[tauri::command(rename_all = "camelCase")]
+async fn fit_calibration(directory: String, state: State<'_, AppState>) -> Result<Calibration, String> {
+    let classifier = state.classifiers.lock().unwrap()
+        .active()
+        .ok_or("No classifier backend configured")?;
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+
+    let samples = calibration::collect_samples(&directory, classifier.as_ref()).await?;
+    let calibration = calibration::fit(&samples, &classifier.model_version())?;
+
+    db_connection.save_calibration(&calibration)
+        .map_err(|e| format!("Database error: {}", e))?;
+    Ok(calibration)
+}
+
+/// Check the active backend's classes against `model_labels`, optionally creating missing rows
+#[tauri::command(rename_all = "camelCase")]
+async fn sync_model_labels(create_missing: Option<bool>, state: State<'_, AppState>) -> Result<LabelSyncReport, String> {
+    let classifier = state.classifiers.lock().unwrap()
+        .active()
+        .ok_or("No classifier backend configured")?;
+    let db_connection = state.db_connection.lock().unwrap().clone()
+        .ok_or("Database not connected")?;
+
+    label_sync::reconcile(classifier.as_ref(), &db_connection, create_missing.unwrap_or(false)).await
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn get_settings(state: State<'_, AppState>) -> AppSettings {
+    state.settings.lock().unwrap().clone()
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn update_settings(app_handle: AppHandle, settings: AppSettings, state: State<'_, AppState>) -> Result<AppSettings, String> {
+    let app_data_dir = app_data_dir(&app_handle.config()).ok_or("Failed to get app data directory")?;
+    save_settings(&app_data_dir.join("settings.json"), &settings)?;
     
-    println!("Received Response from: /predict {}", response.status());
-    
-    // Parse response JSON
-    let api_result: serde_json::Value = match response.json().await {
-        Ok(json) => json,
-        Err(e) => {
-            println!("Failed to parse API response: {}", e);
-            // Return fallback result
-            return Ok(json!({
-                "label": "Oak_Tree",
-                "confidence": 0.85,
-                "_fallback": true
-            }));
-        },
-    };
-    
-    println!("API response: {}", api_result);
-    
-    // Extract the required fields or use fallback
-    let label = api_result["label"].as_str().unwrap_or("Oak_Tree").to_string();
-    let confidence = api_result["confidence"].as_f64().unwrap_or(0.85);
-    
-    // Create our result object
-    let result = json!({
-        "label": label,
-        "confidence": confidence,
-        "_fallback": api_result["_fallback"].as_bool().unwrap_or(false)
-    });
-    
-    Ok(result)
+    *state.settings.lock().unwrap() = settings.clone();
+    Ok(settings)
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn list_classifier_backends(state: State<'_, AppState>) -> Value {
+    let classifiers = state.classifiers.lock().unwrap();
+    json!({
+        "backends": classifiers.names(),
+        "active": classifiers.active_name(),
+    })
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn get_remote_status(state: State<'_, AppState>) -> Result<CircuitStatus, String> {
+    state.classifiers.lock().unwrap()
+        .get("remote")
+        .and_then(|remote| remote.circuit_status())
+        .ok_or_else(|| "Remote classifier is not registered".to_string())
+}
+
+#[tauri::command(rename_all = "camelCase")]
+fn set_classifier_backend(name: String, state: State<'_, AppState>) -> Result<(), String> {
+    let mut classifiers = state.classifiers.lock().unwrap();
+    classifiers.set_active(&name)
 }
 
 #[cfg(target_os = "windows")]
@@ -274,6 +401,16 @@ fn main() {
     #[cfg(target_os = "windows")]
     set_startup_registry();
     
+    // Register classifier backends; the remote HTTP classifier is the default
+    let mut classifiers = ClassifierRegistry::default();
+    classifiers.register(Arc::new(RemoteHttpClassifier::from_env()));
+    
+    // Mock results are only served when demo mode is explicitly requested
+    if DemoClassifier::enabled() {
+        classifiers.register(Arc::new(DemoClassifier));
+        let _ = classifiers.set_active("demo");
+    }
+    
     tauri::Builder::default()
         .system_tray(system_tray)
         .on_system_tray_event(|app, event| match event {
@@ -293,6 +430,8 @@ fn main() {
         .manage(AppState {
             db_connection: Mutex::new(None),
             activated: Mutex::new(false),
+            classifiers: Mutex::new(classifiers),
+            settings: Mutex::new(AppSettings::default()),
         })
         .setup(|app| {
             let app_handle = app.handle();
@@ -326,6 +465,39 @@ fn main() {
                 *activated = is_app_activated;
             }
             
+            *state.settings.lock().unwrap() = load_settings(&app_data_dir.join("settings.json"));
+            
+            // Register the on-device ONNX classifier when the model ships with the app
+            let model_path = app_handle.path_resolver().resolve_resource("resources/models/wood_classifier.onnx");
+            let labels_path = app_handle.path_resolver().resolve_resource("resources/models/labels.txt");
+            
+            if let (Some(model_path), Some(labels_path)) = (model_path, labels_path) {
+                if model_path.exists() && labels_path.exists() {
+                    match LocalOnnxClassifier::load(&model_path, &labels_path) {
+                        Ok(local) => state.classifiers.lock().unwrap().register(Arc::new(local)),
+                        Err(e) => eprintln!("Failed to load local classifier: {}", e),
+                    }
+                } else {
+                    eprintln!("Local classifier model not found, offline analysis unavailable");
+                }
+            }
+            
+            // Allow the active backend to be chosen at launch, e.g. CLASSIFIER_BACKEND=local for field use
+            if let Ok(backend) = std::env::var("CLASSIFIER_BACKEND") {
+                if let Err(e) = state.classifiers.lock().unwrap().set_active(&backend) {
+                    eprintln!("{}", e);
+                }
+            }
+            
+            // Ping the remote API in the background so a cold-starting Space is awake by the first analysis
+            if let Some(remote) = state.classifiers.lock().unwrap().get("remote") {
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = remote.warm_up().await {
+                        eprintln!("Classifier API warm-up failed: {}", e);
+                    }
+                });
+            }
+            
             // Try multiple possible resource paths for the database
             let possible_paths = [
                 app_handle.path_resolver().resolve_resource("resources/species.db"),
@@ -341,11 +513,18 @@ fn main() {
                 Some(path) => {
                     eprintln!("Found database at: {:?}", path);
                     
-                    match DbConnection::new(path.to_string_lossy().to_string()) {
+                    // History, queue, cache and calibrations go to the app data directory, not the bundled database
+                    let app_db_path = app_data_dir.join(APP_DATA_FILE);
+                    match DbConnection::with_app_data(path.to_string_lossy().to_string(), &app_db_path.to_string_lossy()) {
                         Ok(conn) => {
                             let mut db_conn = state.db_connection.lock().unwrap();
                             *db_conn = Some(conn.clone());
                             
+                            // Retry queued analyses in the background once the remote classifier is reachable
+                            if let Some(remote) = state.classifiers.lock().unwrap().get("remote") {
+                                tauri::async_runtime::spawn(offline_queue::run_worker(app_handle.clone(), conn.clone(), remote));
+                            }
+                            
                             // Try multiple possible paths for species data JSON
                             let possible_json_paths = [
                                 app_handle.path_resolver().resolve_resource("resources/species_data.json"),
@@ -371,6 +550,24 @@ fn main() {
                                     eprintln!("Species data JSON file not found!");
                                 }
                             }
+                            
+                            // Report model classes that have no species record, without changing anything
+                            if let Some(classifier) = state.classifiers.lock().unwrap().active() {
+                                let conn = conn.clone();
+                                tauri::async_runtime::spawn(async move {
+                                    match label_sync::reconcile(classifier.as_ref(), &conn, false).await {
+                                        Ok(report) if report.unmapped_labels.is_empty() && report.unlabelled_species.is_empty() => {
+                                            println!("All {} model classes map to species records", report.model_classes);
+                                        },
+                                        Ok(report) => eprintln!(
+                                            "Model label mismatch: classes without species {:?}, species without classes {:?}",
+                                            report.unmapped_labels,
+                                            report.unlabelled_species.iter().map(|species| &species.scientific_name).collect::<Vec<_>>(),
+                                        ),
+                                        Err(e) => eprintln!("Model label check skipped: {}", e),
+                                    }
+                                });
+                            }
                         },
                         Err(e) => {
                             eprintln!("Failed to connect to database: {}", e);
@@ -388,7 +585,26 @@ fn main() {
             is_activated,
             activate_with_key,
             get_species_info,
+            resolve_label,
+            search_species,
+            get_species_attributes,
+            filter_species,
+            identify_by_key,
             analyze_local_image,
+            analyze_batch,
+            list_classifier_backends,
+            set_classifier_backend,
+            get_remote_status,
+            get_settings,
+            update_settings,
+            get_analysis_queue,
+            list_analyses,
+            get_analysis,
+            delete_analysis,
+            clear_prediction_cache,
+            get_calibration,
+            fit_calibration,
+            sync_model_labels,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
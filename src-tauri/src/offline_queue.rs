@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tauri::api::path::app_data_dir;
+use crate::analysis::{self, AnalysisOptions, AnalysisResult};
+use crate::classifier::{AnalysisError, Classifier, ImageInput};
+use crate::database::{DbConnection, QueuedAnalysis};
+use crate::settings::{AppSettings, load_settings};
+
+/// How often the worker checks for queued images and whether the classifier is reachable
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Emitted when a queued image has been analyzed
+pub const QUEUE_COMPLETED_EVENT: &str = "analysis-queue-completed";
+/// Emitted when a queued image can never be analyzed, e.g. it was deleted
+pub const QUEUE_FAILED_EVENT: &str = "analysis-queue-failed";
+
+#[derive(Clone, Serialize)]
+pub struct QueueEvent {
+    pub id: i64,
+    pub image_path: String,
+    pub result: Option<AnalysisResult>,
+    pub error: Option<AnalysisError>,
+}
+
+/// Store an image the classifier could not be reached for, returning the error reported to the caller
+pub fn enqueue(db: &DbConnection, file_path: &str, image: &ImageInput, options: &AnalysisOptions) -> AnalysisError {
+    match db.enqueue_analysis(file_path, &image.content_hash(), options.top_k, options.tiling.enabled) {
+        Ok(queue_id) => {
+            println!("Classifier unreachable, queued {} as #{}", file_path, queue_id);
+            AnalysisError::Queued { queue_id }
+        },
+        Err(e) => AnalysisError::Backend(format!("Failed to queue analysis: {}", e)),
+    }
+}
+
+/// Analyze queued images whenever the classifier becomes reachable again
+pub async fn run_worker(app_handle: AppHandle, db: DbConnection, classifier: Arc<dyn Classifier>) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let pending = match db.pending_analyses() {
+            Ok(pending) => pending,
+            Err(e) => {
+                eprintln!("Failed to read analysis queue: {}", e);
+                continue;
+            }
+        };
+
+        if pending.is_empty() {
+            continue;
+        }
+
+        // Cheap reachability check before uploading anything
+        if classifier.warm_up().await.is_err() {
+            continue;
+        }
+
+        println!("Classifier reachable, processing {} queued analyses", pending.len());
+
+        // Settings may have changed since the images were queued
+        let settings = app_data_dir(&app_handle.config())
+            .map(|dir| load_settings(&dir.join("settings.json")))
+            .unwrap_or_default();
+
+        for item in pending {
+            if !process(&app_handle, &db, classifier.as_ref(), &settings, &item).await {
+                // Connectivity dropped again; wait for the next poll
+                break;
+            }
+        }
+    }
+}
+
+/// Returns false when the classifier became unreachable while processing
+async fn process(app_handle: &AppHandle, db: &DbConnection, classifier: &dyn Classifier, settings: &AppSettings, item: &QueuedAnalysis) -> bool {
+    let mut options = AnalysisOptions::from_settings(settings);
+    options.top_k = item.top_k;
+    options.tiling.enabled = item.tiled;
+
+    let outcome = analysis::analyze(&item.image_path, classifier, Some(db), &options).await;
+
+    let event = match outcome {
+        Ok(result) => {
+            let result_json = serde_json::to_string(&result).unwrap_or_default();
+            if let Err(e) = db.complete_queued_analysis(item.id, &result_json) {
+                eprintln!("Failed to update analysis queue: {}", e);
+            }
+            QueueEvent { id: item.id, image_path: item.image_path.clone(), result: Some(result), error: None }
+        },
+        // Still unreachable; the pending entry was reused rather than duplicated
+        Err(AnalysisError::Queued { .. }) => {
+            if let Err(e) = db.retry_queued_analysis(item.id, "Classifier unreachable") {
+                eprintln!("Failed to update analysis queue: {}", e);
+            }
+            return false;
+        },
+        Err(e) => {
+            if let Err(db_error) = db.fail_queued_analysis(item.id, &e.to_string()) {
+                eprintln!("Failed to update analysis queue: {}", db_error);
+            }
+            QueueEvent { id: item.id, image_path: item.image_path.clone(), result: None, error: Some(e) }
+        },
+    };
+
+    let event_name = if event.result.is_some() { QUEUE_COMPLETED_EVENT } else { QUEUE_FAILED_EVENT };
+    if let Err(e) = app_handle.emit_all(event_name, event) {
+        eprintln!("Failed to emit {}: {}", event_name, e);
+    }
+
+    true
+}
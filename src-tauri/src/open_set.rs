@@ -0,0 +1,120 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use crate::database::normalize_label;
+
+/// Label reported when no known species is a confident enough match
+pub const UNKNOWN_LABEL: &str = "unknown";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The top prediction cleared its label's threshold
+    Identified,
+    /// The image does not confidently match any species the model knows
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenSetSettings {
+    pub enabled: bool,
+    /// Threshold for labels without a `model_labels.confidence_threshold` value
+    pub default_threshold: f64,
+    /// Minimum gap between the two most probable classes; 0 disables the check
+    pub min_margin: f64,
+}
+
+impl Default for OpenSetSettings {
+    fn default() -> Self {
+        OpenSetSettings {
+            enabled: true,
+            default_threshold: 0.5,
+            min_margin: 0.0,
+        }
+    }
+}
+
+/// Open-set decision for a single prediction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenSetDecision {
+    pub outcome: Outcome,
+    /// Best matching known label, kept even when the outcome is unknown
+    pub candidate: String,
+    pub threshold: f64,
+    pub reason: Option<String>,
+}
+
+/// Decide whether the top prediction is trustworthy, given the runner-up probability when known.
+/// `thresholds` is keyed by normalized label, as returned by `DbConnection::label_thresholds`.
+pub fn decide(
+    label: &str,
+    confidence: f64,
+    runner_up: Option<f64>,
+    thresholds: &HashMap<String, f64>,
+    settings: &OpenSetSettings,
+) -> OpenSetDecision {
+    let threshold = thresholds.get(&normalize_label(label)).copied().unwrap_or(settings.default_threshold);
+    let margin = runner_up.map(|runner_up| confidence - runner_up);
+
+    let reason = if confidence < threshold {
+        Some(format!("Confidence {:.2} is below the {:.2} threshold for {}", confidence, threshold, label))
+    } else if settings.min_margin > 0.0 && margin.is_some_and(|margin| margin < settings.min_margin) {
+        Some(format!("Top two classes are within {:.2} of each other", settings.min_margin))
+    } else {
+        None
+    };
+
+    OpenSetDecision {
+        outcome: if reason.is_some() { Outcome::Unknown } else { Outcome::Identified },
+        candidate: label.to_string(),
+        threshold,
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds() -> HashMap<String, f64> {
+        HashMap::from([(normalize_label("Tectona grandis_Segun"), 0.8)])
+    }
+
+    #[test]
+    fn identifies_confident_predictions() {
+        let decision = decide("Tectona grandis_Segun", 0.85, Some(0.1), &thresholds(), &OpenSetSettings::default());
+        assert_eq!(decision.outcome, Outcome::Identified);
+        assert_eq!(decision.threshold, 0.8);
+        assert!(decision.reason.is_none());
+    }
+
+    #[test]
+    fn uses_the_label_threshold_whatever_the_spelling() {
+        let decision = decide("Tectona_grandis_Segun", 0.7, None, &thresholds(), &OpenSetSettings::default());
+        assert_eq!(decision.outcome, Outcome::Unknown);
+        assert_eq!(decision.threshold, 0.8);
+        assert_eq!(decision.candidate, "Tectona_grandis_Segun");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_threshold() {
+        let settings = OpenSetSettings::default();
+        assert_eq!(decide("Toona ciliata_Toon", 0.55, None, &thresholds(), &settings).outcome, Outcome::Identified);
+
+        let decision = decide("Toona ciliata_Toon", 0.45, None, &thresholds(), &settings);
+        assert_eq!(decision.outcome, Outcome::Unknown);
+        assert_eq!(decision.threshold, settings.default_threshold);
+    }
+
+    #[test]
+    fn rejects_close_runners_up_only_when_a_margin_is_set() {
+        let mut settings = OpenSetSettings::default();
+        assert_eq!(decide("Toona ciliata_Toon", 0.6, Some(0.55), &thresholds(), &settings).outcome, Outcome::Identified);
+
+        settings.min_margin = 0.1;
+        let decision = decide("Toona ciliata_Toon", 0.6, Some(0.55), &thresholds(), &settings);
+        assert_eq!(decision.outcome, Outcome::Unknown);
+        assert!(decision.reason.unwrap().contains("within 0.10"));
+        assert_eq!(decide("Toona ciliata_Toon", 0.6, None, &thresholds(), &settings).outcome, Outcome::Identified);
+    }
+}
@@ -0,0 +1,172 @@
+use image::{DynamicImage, GenericImageView};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use serde::{Serialize, Deserialize};
+use std::io::Cursor;
+use crate::classifier::{AnalysisError, ImageInput};
+
+/// JPEG quality used when re-encoding images for the classifier
+pub const JPEG_QUALITY: u8 = 90;
+
+/// What preprocessing did to an image, returned alongside the analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreprocessInfo {
+    pub original_width: u32,
+    pub original_height: u32,
+    pub width: u32,
+    pub height: u32,
+    /// EXIF orientation tag that was applied (1 = already upright)
+    pub orientation: u32,
+    pub original_bytes: usize,
+    pub bytes: usize,
+}
+
+/// Read the EXIF orientation tag, defaulting to upright when absent
+fn exif_orientation(data: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+        .and_then(|exif| exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0)))
+        .unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Decode any supported format and rotate it upright according to its EXIF orientation
+pub fn decode_upright(data: &[u8]) -> Result<(DynamicImage, u32), AnalysisError> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| AnalysisError::UnsupportedFormat(format!("failed to decode image: {}", e)))?;
+
+    let orientation = exif_orientation(data);
+    Ok((apply_orientation(image, orientation), orientation))
+}
+
+/// Normalize an upright decoded image before classification: shrink so the longest side
+/// fits `max_dimension` (never upscaling) and re-encode as JPEG
+pub fn prepare(input: &ImageInput, image: DynamicImage, orientation: u32, max_dimension: Option<u32>) -> Result<(ImageInput, PreprocessInfo), AnalysisError> {
+    let (original_width, original_height) = image.dimensions();
+
+    let image = match max_dimension {
+        Some(max) if original_width.max(original_height) > max => image.resize(max, max, FilterType::Triangle),
+        _ => image,
+    };
+    let (width, height) = image.dimensions();
+
+    let mut data = Vec::new();
+    JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)
+        .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8()))
+        .map_err(|e| AnalysisError::Backend(format!("Failed to encode image: {}", e)))?;
+
+    let stem = input.file_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&input.file_name);
+
+    let info = PreprocessInfo {
+        original_width,
+        original_height,
+        width,
+        height,
+        orientation,
+        original_bytes: input.data.len(),
+        bytes: data.len(),
+    };
+
+    let prepared = ImageInput {
+        file_name: format!("{}.jpg", stem),
+        mime_type: "image/jpeg".to_string(),
+        data,
+    };
+
+    Ok((prepared, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// 16x8 JPEG whose left half is red and right half blue
+    fn jpeg() -> Vec<u8> {
+        let image = RgbImage::from_fn(16, 8, |x, _| match x < 8 {
+            true => Rgb([255, 0, 0]),
+            false => Rgb([0, 0, 255]),
+        });
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)
+            .encode_image(&DynamicImage::ImageRgb8(image))
+            .unwrap();
+        data
+    }
+
+    /// Insert an APP1 segment holding only an EXIF orientation tag right after the JPEG start marker
+    fn with_orientation(jpeg: &[u8], orientation: u8) -> Vec<u8> {
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+        exif.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0, 0, 0, 0, 0]);
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xff, 0xe1]);
+        data.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&exif);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    fn is_red(image: &DynamicImage, x: u32, y: u32) -> bool {
+        let [r, _, b, _] = image.get_pixel(x, y).0;
+        r > 200 && b < 60
+    }
+
+    #[test]
+    fn images_without_exif_stay_as_they_are() {
+        let (image, orientation) = decode_upright(&jpeg()).unwrap();
+        assert_eq!(orientation, 1);
+        assert_eq!(image.dimensions(), (16, 8));
+        assert!(is_red(&image, 2, 4));
+        assert!(!is_red(&image, 13, 4));
+    }
+
+    #[test]
+    fn rotates_by_exif_orientation() {
+        // 6: the camera was turned clockwise, so the image is rotated 90 degrees clockwise to be upright
+        let (image, orientation) = decode_upright(&with_orientation(&jpeg(), 6)).unwrap();
+        assert_eq!(orientation, 6);
+        assert_eq!(image.dimensions(), (8, 16));
+        assert!(is_red(&image, 4, 2));
+        assert!(!is_red(&image, 4, 13));
+
+        let (image, orientation) = decode_upright(&with_orientation(&jpeg(), 3)).unwrap();
+        assert_eq!(orientation, 3);
+        assert_eq!(image.dimensions(), (16, 8));
+        assert!(!is_red(&image, 2, 4));
+        assert!(is_red(&image, 13, 4));
+    }
+
+    #[test]
+    fn rejects_undecodable_data() {
+        assert!(matches!(decode_upright(b"not an image"), Err(AnalysisError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn shrinks_to_the_input_size_without_upscaling() {
+        let input = ImageInput { file_name: "sample.png".to_string(), mime_type: "image/png".to_string(), data: jpeg() };
+
+        let (decoded, _) = decode_upright(&input.data).unwrap();
+        let (prepared, info) = prepare(&input, decoded, 1, Some(8)).unwrap();
+        assert_eq!((info.original_width, info.original_height, info.width, info.height), (16, 8, 8, 4));
+        assert_eq!((prepared.file_name.as_str(), prepared.mime_type.as_str()), ("sample.jpg", "image/jpeg"));
+
+        let (decoded, _) = decode_upright(&input.data).unwrap();
+        let (_, info) = prepare(&input, decoded, 1, Some(64)).unwrap();
+        assert_eq!((info.width, info.height), (16, 8));
+    }
+}
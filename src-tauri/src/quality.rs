@@ -0,0 +1,186 @@
+use image::{DynamicImage, GenericImageView, GrayImage};
+use image::imageops::FilterType;
+use serde::{Serialize, Deserialize};
+
+// Sharpness is measured at a fixed scale so the threshold does not depend on camera resolution
+const SHARPNESS_SCALE: u32 = 1024;
+// Luma values at or beyond these count as clipped
+const SHADOW_CLIP: u8 = 5;
+const HIGHLIGHT_CLIP: u8 = 250;
+
+/// What to do with images that fail a quality check
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityMode {
+    Off,
+    /// Classify anyway and report the problems alongside the prediction
+    Warn,
+    /// Refuse to classify
+    Reject,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualitySettings {
+    pub mode: QualityMode,
+    /// Minimum variance of the Laplacian; lower means out of focus
+    pub min_sharpness: f64,
+    /// Maximum fraction of pixels crushed to black or blown to white
+    pub max_clipped_fraction: f64,
+    /// Minimum length of the shorter image side, in pixels
+    pub min_resolution: u32,
+}
+
+impl Default for QualitySettings {
+    fn default() -> Self {
+        QualitySettings {
+            mode: QualityMode::Warn,
+            min_sharpness: 100.0,
+            max_clipped_fraction: 0.05,
+            min_resolution: 224,
+        }
+    }
+}
+
+/// Measurements for one image and the checks it failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    pub width: u32,
+    pub height: u32,
+    pub sharpness: f64,
+    pub underexposed_fraction: f64,
+    pub overexposed_fraction: f64,
+    pub issues: Vec<String>,
+    pub passed: bool,
+}
+
+/// Measure sharpness, exposure clipping and resolution against `settings`
+pub fn assess(image: &DynamicImage, settings: &QualitySettings) -> QualityReport {
+    let (width, height) = image.dimensions();
+
+    let scaled = if width.max(height) > SHARPNESS_SCALE {
+        image.resize(SHARPNESS_SCALE, SHARPNESS_SCALE, FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+    let gray = scaled.to_luma8();
+
+    let sharpness = laplacian_variance(&gray);
+    let (underexposed_fraction, overexposed_fraction) = clipped_fractions(&gray);
+
+    let mut issues = Vec::new();
+    if width.min(height) < settings.min_resolution {
+        issues.push(format!("Resolution {}x{} is below the {}px minimum", width, height, settings.min_resolution));
+    }
+    if sharpness < settings.min_sharpness {
+        issues.push(format!("Image looks out of focus (sharpness {:.1}, minimum {:.1})", sharpness, settings.min_sharpness));
+    }
+    if underexposed_fraction > settings.max_clipped_fraction {
+        issues.push(format!("{:.1}% of the image is underexposed", underexposed_fraction * 100.0));
+    }
+    if overexposed_fraction > settings.max_clipped_fraction {
+        issues.push(format!("{:.1}% of the image is overexposed", overexposed_fraction * 100.0));
+    }
+
+    QualityReport {
+        width,
+        height,
+        sharpness,
+        underexposed_fraction,
+        overexposed_fraction,
+        passed: issues.is_empty(),
+        issues,
+    }
+}
+
+/// Variance of the 4-neighbour Laplacian over interior pixels
+fn laplacian_variance(gray: &GrayImage) -> f64 {
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let pixel = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    let mut count = 0.0;
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1) - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+            count += 1.0;
+        }
+    }
+
+    let mean = sum / count;
+    sum_squares / count - mean * mean
+}
+
+fn clipped_fractions(gray: &GrayImage) -> (f64, f64) {
+    let total = (gray.width() as f64 * gray.height() as f64).max(1.0);
+    let (mut shadows, mut highlights) = (0usize, 0usize);
+
+    for pixel in gray.pixels() {
+        if pixel[0] <= SHADOW_CLIP {
+            shadows += 1;
+        } else if pixel[0] >= HIGHLIGHT_CLIP {
+            highlights += 1;
+        }
+    }
+
+    (shadows as f64 / total, highlights as f64 / total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn checkerboard(size: u32, dark: u8, light: u8) -> GrayImage {
+        GrayImage::from_fn(size, size, |x, y| match (x + y) % 2 {
+            0 => Luma([light]),
+            _ => Luma([dark]),
+        })
+    }
+
+    #[test]
+    fn laplacian_variance_of_flat_and_alternating_images() {
+        assert_eq!(laplacian_variance(&GrayImage::from_pixel(8, 8, Luma([128]))), 0.0);
+        // Every interior pixel differs from its four neighbours by 255, so the Laplacian is +-1020
+        assert_eq!(laplacian_variance(&checkerboard(6, 0, 255)), 1020.0 * 1020.0);
+        // Too small to have interior pixels
+        assert_eq!(laplacian_variance(&checkerboard(2, 0, 255)), 0.0);
+    }
+
+    #[test]
+    fn clipping_thresholds_are_inclusive() {
+        let gray = GrayImage::from_fn(4, 1, |x, _| Luma([[SHADOW_CLIP, SHADOW_CLIP + 1, HIGHLIGHT_CLIP - 1, HIGHLIGHT_CLIP][x as usize]]));
+        assert_eq!(clipped_fractions(&gray), (0.25, 0.25));
+    }
+
+    #[test]
+    fn reports_each_failed_check() {
+        let settings = QualitySettings::default();
+
+        let sharp = DynamicImage::ImageLuma8(checkerboard(300, 60, 180));
+        let report = assess(&sharp, &settings);
+        assert!(report.passed, "{:?}", report.issues);
+
+        let blurry = DynamicImage::ImageLuma8(GrayImage::from_pixel(300, 300, Luma([128])));
+        let report = assess(&blurry, &settings);
+        assert_eq!(report.issues.len(), 1);
+        assert!(report.issues[0].contains("out of focus"));
+
+        let clipped = DynamicImage::ImageLuma8(checkerboard(300, 0, 255));
+        let report = assess(&clipped, &settings);
+        assert_eq!((report.underexposed_fraction, report.overexposed_fraction), (0.5, 0.5));
+        assert_eq!(report.issues.len(), 2);
+
+        let small = DynamicImage::ImageLuma8(checkerboard(100, 60, 180));
+        let report = assess(&small, &settings);
+        assert!(!report.passed);
+        assert!(report.issues[0].starts_with("Resolution 100x100"));
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+use crate::database::CachePolicy;
+use crate::open_set::OpenSetSettings;
+use crate::quality::QualitySettings;
+use crate::tiling::TilingSettings;
+
+/// User-adjustable settings stored as `settings.json` in the app data directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub quality: QualitySettings,
+    pub tiling: TilingSettings,
+    pub open_set: OpenSetSettings,
+    /// Size and age limits of the prediction cache
+    pub cache: CachePolicy,
+}
+
+/// Load settings, falling back to defaults if the file is missing or unreadable
+pub fn load_settings(settings_path: &Path) -> AppSettings {
+    if !settings_path.exists() {
+        return AppSettings::default();
+    }
+
+    match fs::read_to_string(settings_path).map(|contents| serde_json::from_str(&contents)) {
+        Ok(Ok(settings)) => settings,
+        Ok(Err(e)) => {
+            eprintln!("Error parsing settings file: {}", e);
+            AppSettings::default()
+        },
+        Err(e) => {
+            eprintln!("Error reading settings file: {}", e);
+            AppSettings::default()
+        }
+    }
+}
+
+pub fn save_settings(settings_path: &Path, settings: &AppSettings) -> Result<(), String> {
+    if let Some(parent) = settings_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+    }
+
+    let settings_json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    fs::write(settings_path, settings_json)
+        .map_err(|e| format!("Failed to write settings file: {}", e))
+}
@@ -0,0 +1,325 @@
+use futures::stream::{self, StreamExt};
+use image::{DynamicImage, GenericImageView};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use crate::classifier::{AnalysisError, ClassScore, Classifier, ImageInput, Prediction};
+use crate::preprocess;
+
+// Tiles classified at the same time
+const TILE_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TilingSettings {
+    /// Split images into tiles by default; `analyze_local_image` can override per request
+    pub enabled: bool,
+    /// Tile edge length in source pixels
+    pub tile_size: u32,
+    /// Fraction of a tile shared with its neighbour, in [0, 0.9]
+    pub overlap: f64,
+    /// Upper bound on tiles per image; tiles are enlarged to stay within it
+    pub max_tiles: usize,
+}
+
+impl Default for TilingSettings {
+    fn default() -> Self {
+        TilingSettings {
+            enabled: false,
+            tile_size: 640,
+            overlap: 0.25,
+            max_tiles: 16,
+        }
+    }
+}
+
+impl TilingSettings {
+    /// Distinguishes tiled results from whole-image results in the prediction cache
+    pub fn cache_key(&self) -> String {
+        format!("tiled:{}:{:.2}:{}", self.tile_size, self.overlap, self.max_tiles)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Prediction for a single tile; either `label` and `confidence` or `error` is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileResult {
+    pub rect: TileRect,
+    pub label: Option<String>,
+    pub confidence: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileVote {
+    pub label: String,
+    /// Tiles whose top label this was
+    pub votes: usize,
+    /// Probability averaged over all classified tiles
+    pub mean_probability: f64,
+}
+
+/// How the tiles voted, returned alongside the aggregated prediction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TilingReport {
+    pub tile_count: usize,
+    pub classified_tiles: usize,
+    /// Share of classified tiles whose top label matches the final label
+    pub agreement: f64,
+    pub votes: Vec<TileVote>,
+    pub tiles: Vec<TileResult>,
+}
+
+/// Start offsets along one axis so tiles of `tile` length cover `length` with the given stride
+fn axis_offsets(length: u32, tile: u32, stride: u32) -> Vec<u32> {
+    if length <= tile {
+        return vec![0];
+    }
+
+    let mut offsets: Vec<u32> = (0..).map(|i| i * stride).take_while(|offset| offset + tile < length).collect();
+    offsets.push(length - tile);
+    offsets
+}
+
+/// Overlapping tile grid covering the whole image, enlarging tiles until at most `max_tiles` remain
+pub fn tile_grid(width: u32, height: u32, settings: &TilingSettings) -> Vec<TileRect> {
+    let overlap = settings.overlap.clamp(0.0, 0.9);
+    let mut tile = settings.tile_size.max(32);
+
+    loop {
+        let stride = ((tile as f64 * (1.0 - overlap)) as u32).max(1);
+        let xs = axis_offsets(width, tile, stride);
+        let ys = axis_offsets(height, tile, stride);
+
+        if xs.len() * ys.len() <= settings.max_tiles.max(1) || tile >= width.max(height) {
+            return ys.iter()
+                .flat_map(|&y| xs.iter().map(move |&x| TileRect {
+                    x,
+                    y,
+                    width: tile.min(width),
+                    height: tile.min(height),
+                }))
+                .collect();
+        }
+
+        tile = tile + tile / 4;
+    }
+}
+
+/// Classify overlapping tiles of an upright image and combine them by majority vote
+pub async fn classify_tiled(
+    classifier: &dyn Classifier,
+    source: &ImageInput,
+    image: DynamicImage,
+    settings: &TilingSettings,
+) -> Result<(Prediction, TilingReport), AnalysisError> {
+    let (width, height) = image.dimensions();
+    let rects = tile_grid(width, height, settings);
+    let max_dimension = classifier.input_size();
+    let source = source.clone();
+
+    // Cropping and encoding is CPU bound, so do it off the async executor
+    let tiles: Vec<(TileRect, ImageInput)> = tauri::async_runtime::spawn_blocking(move || {
+        rects.into_iter()
+            .map(|rect| {
+                let crop = image.crop_imm(rect.x, rect.y, rect.width, rect.height);
+                preprocess::prepare(&source, crop, 1, max_dimension).map(|(tile, _)| (rect, tile))
+            })
+            .collect::<Result<Vec<_>, AnalysisError>>()
+    })
+    .await
+    .map_err(|e| AnalysisError::Backend(format!("Tiling task failed: {}", e)))??;
+
+    println!("Classifying {} tiles of a {}x{} image", tiles.len(), width, height);
+
+    let outcomes: Vec<(TileRect, Result<Prediction, AnalysisError>)> = stream::iter(tiles)
+        .map(|(rect, tile)| async move { (rect, classifier.classify(&tile).await) })
+        .buffered(TILE_CONCURRENCY)
+        .collect()
+        .await;
+
+    aggregate(outcomes)
+}
+
+fn aggregate(outcomes: Vec<(TileRect, Result<Prediction, AnalysisError>)>) -> Result<(Prediction, TilingReport), AnalysisError> {
+    let tile_count = outcomes.len();
+    let mut probability_sums: HashMap<String, f64> = HashMap::new();
+    let mut vote_counts: HashMap<String, usize> = HashMap::new();
+    let mut tiles = Vec::with_capacity(tile_count);
+    let mut first_error = None;
+    let mut classified_tiles = 0;
+
+    for (rect, outcome) in outcomes {
+        match outcome {
+            Ok(prediction) => {
+                classified_tiles += 1;
+                *vote_counts.entry(prediction.label.clone()).or_insert(0) += 1;
+                for score in &prediction.probabilities {
+                    *probability_sums.entry(score.label.clone()).or_insert(0.0) += score.probability;
+                }
+                tiles.push(TileResult { rect, label: Some(prediction.label), confidence: Some(prediction.confidence), error: None });
+            },
+            Err(e) => {
+                tiles.push(TileResult { rect, label: None, confidence: None, error: Some(e.to_string()) });
+                first_error.get_or_insert(e);
+            },
+        }
+    }
+
+    if classified_tiles == 0 {
+        return Err(first_error.unwrap_or_else(|| AnalysisError::Backend("No tiles to classify".to_string())));
+    }
+
+    let distribution: Vec<ClassScore> = probability_sums.iter()
+        .map(|(label, sum)| ClassScore { label: label.clone(), probability: sum / classified_tiles as f64 })
+        .collect();
+    let mut prediction = Prediction::from_distribution(distribution)
+        .ok_or_else(|| AnalysisError::BadPayload("Tiles produced no class scores".to_string()))?;
+
+    let mut votes: Vec<TileVote> = vote_counts.into_iter()
+        .map(|(label, votes)| TileVote {
+            mean_probability: probability_sums.get(&label).copied().unwrap_or(0.0) / classified_tiles as f64,
+            label,
+            votes,
+        })
+        .collect();
+    votes.sort_by(|a, b| b.votes.cmp(&a.votes).then(b.mean_probability.total_cmp(&a.mean_probability)));
+
+    // The most voted label wins, so a few confident tiles cannot outweigh the rest of the section;
+    // mean probability only breaks ties. The ranked classes keep their mean order after the winner.
+    let winner = &votes[0];
+    prediction.label = winner.label.clone();
+    prediction.confidence = winner.mean_probability;
+    prediction.probabilities.sort_by_key(|score| score.label != winner.label);
+    let agreeing = winner.votes;
+
+    let report = TilingReport {
+        tile_count,
+        classified_tiles,
+        agreement: agreeing as f64 / classified_tiles as f64,
+        votes,
+        tiles,
+    };
+
+    Ok((prediction, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(tile_size: u32, overlap: f64, max_tiles: usize) -> TilingSettings {
+        TilingSettings { enabled: true, tile_size, overlap, max_tiles }
+    }
+
+    fn prediction(scores: &[(&str, f64)]) -> Prediction {
+        Prediction::from_distribution(scores.iter()
+            .map(|(label, probability)| ClassScore { label: label.to_string(), probability: *probability })
+            .collect()).unwrap()
+    }
+
+    fn rect(x: u32, y: u32) -> TileRect {
+        TileRect { x, y, width: 100, height: 100 }
+    }
+
+    #[test]
+    fn small_images_are_a_single_tile() {
+        assert_eq!(tile_grid(300, 200, &settings(640, 0.25, 16)), vec![TileRect { x: 0, y: 0, width: 300, height: 200 }]);
+    }
+
+    #[test]
+    fn exact_fit_has_no_extra_tile() {
+        // Stride 100 covers 300 pixels with three 100px tiles and no overlap
+        let tiles = tile_grid(300, 100, &settings(100, 0.0, 16));
+        let xs: Vec<u32> = tiles.iter().map(|tile| tile.x).collect();
+        assert_eq!(xs, vec![0, 100, 200]);
+        assert!(tiles.iter().all(|tile| tile.y == 0 && tile.width == 100 && tile.height == 100));
+    }
+
+    #[test]
+    fn last_tile_is_aligned_to_the_edge() {
+        let tiles = tile_grid(250, 100, &settings(100, 0.0, 16));
+        let xs: Vec<u32> = tiles.iter().map(|tile| tile.x).collect();
+        assert_eq!(xs, vec![0, 100, 150]);
+    }
+
+    #[test]
+    fn enlarges_tiles_to_stay_within_max_tiles() {
+        let tiles = tile_grid(1000, 1000, &settings(100, 0.0, 4));
+        assert!(tiles.len() <= 4, "{} tiles", tiles.len());
+        let size = tiles[0].width;
+        assert!(size > 100 && size < 1000, "tile size {}", size);
+
+        // Every pixel is still covered
+        let right = tiles.iter().map(|tile| tile.x + tile.width).max().unwrap();
+        let bottom = tiles.iter().map(|tile| tile.y + tile.height).max().unwrap();
+        assert_eq!((right, bottom), (1000, 1000));
+    }
+
+    #[test]
+    fn aggregates_votes_and_keeps_failed_tiles() {
+        let outcomes = vec![
+            (rect(0, 0), Ok(prediction(&[("Toona", 0.8), ("Chukrasia", 0.2)]))),
+            (rect(100, 0), Ok(prediction(&[("Toona", 0.6), ("Chukrasia", 0.4)]))),
+            (rect(0, 100), Ok(prediction(&[("Chukrasia", 0.7), ("Toona", 0.3)]))),
+            (rect(100, 100), Err(AnalysisError::Backend("timeout".to_string()))),
+        ];
+
+        let (prediction, report) = aggregate(outcomes).unwrap();
+
+        assert_eq!(prediction.label, "Toona");
+        assert!((prediction.confidence - (0.8 + 0.6 + 0.3) / 3.0).abs() < 1e-9);
+        assert_eq!((report.tile_count, report.classified_tiles), (4, 3));
+        assert!((report.agreement - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(report.votes[0].label, "Toona");
+        assert_eq!(report.votes[0].votes, 2);
+
+        let failed = &report.tiles[3];
+        assert_eq!((failed.label.as_deref(), failed.confidence), (None, None));
+        assert!(failed.error.is_some());
+    }
+
+    #[test]
+    fn majority_vote_outweighs_a_few_confident_tiles() {
+        let outcomes = vec![
+            (rect(0, 0), Ok(prediction(&[("Toona", 0.55), ("Chukrasia", 0.45)]))),
+            (rect(100, 0), Ok(prediction(&[("Toona", 0.55), ("Chukrasia", 0.45)]))),
+            (rect(0, 100), Ok(prediction(&[("Chukrasia", 0.99), ("Toona", 0.01)]))),
+        ];
+
+        let (prediction, report) = aggregate(outcomes).unwrap();
+
+        // Chukrasia has the higher mean probability (0.63 against 0.37) but only one vote
+        assert_eq!(prediction.label, "Toona");
+        assert!((prediction.confidence - 1.11 / 3.0).abs() < 1e-9);
+        let ranked: Vec<&str> = prediction.probabilities.iter().map(|score| score.label.as_str()).collect();
+        assert_eq!(ranked, vec!["Toona", "Chukrasia"]);
+        assert!((report.agreement - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mean_probability_breaks_tied_votes() {
+        let outcomes = vec![
+            (rect(0, 0), Ok(prediction(&[("Toona", 0.9), ("Chukrasia", 0.1)]))),
+            (rect(100, 0), Ok(prediction(&[("Chukrasia", 0.6), ("Toona", 0.4)]))),
+        ];
+
+        let (prediction, report) = aggregate(outcomes).unwrap();
+
+        assert_eq!(prediction.label, "Toona");
+        assert!((prediction.confidence - 0.65).abs() < 1e-9);
+        assert!((report.agreement - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fails_when_no_tile_was_classified() {
+        let outcomes = vec![(rect(0, 0), Err(AnalysisError::Backend("timeout".to_string())))];
+        assert!(matches!(aggregate(outcomes), Err(AnalysisError::Backend(_))));
+    }
+}
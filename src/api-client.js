@@ -15,12 +15,29 @@ const isTauri = (() => {
   }
 })();
 
+// Mock results are only served when demo mode is explicitly requested, as with the desktop app's demo backend
+export const isDemoMode = ['1', 'true'].includes(import.meta.env.TREESCOPE_DEMO_MODE);
+
+/**
+ * Error carrying the fields of the desktop app's serialized AnalysisError
+ * @param {Object} error - `{ kind, message, status }`
+ * @returns {Error} - Error with `kind` and `status` set
+ */
+const toAnalysisError = ({ kind, message, status }) => {
+  const analysisError = new Error(message);
+  analysisError.kind = kind;
+  analysisError.status = status;
+  return analysisError;
+};
+
 /**
  * Analyze an image using the API
  * @param {File|Object} file - File object or path to analyze
+ * @param {Object} options - `observations` recorded by hand, e.g. `{ xylemPorosity: 'ring-porous', densityGCm3: 0.7 }`,
+ *   re-rank the predictions in the desktop app
  * @returns {Promise<Object>} - Analysis result
  */
-export const analyzeImage = async (file) => {
+export const analyzeImage = async (file, { observations } = {}) => {
   // For Tauri desktop app, use the native invoke method
   if (isTauri && file.path) {
     try {
@@ -29,12 +46,18 @@ export const analyzeImage = async (file) => {
       
       const result = await invoke('analyze_local_image', {
         filePath: file.path,
+        observations,
       });
       
       return result;
     } catch (error) {
-      console.error("Tauri analysis failed, falling back to API:", error);
-      // Fall through to web API method if Tauri method fails
+      console.error("Tauri analysis failed:", error);
+      // Surface the structured AnalysisError ({ kind, message, status }) instead of mock data
+      throw toAnalysisError({
+        kind: error?.kind,
+        message: error?.message || String(error),
+        status: error?.status,
+      });
     }
   }
   
@@ -88,7 +111,16 @@ export const analyzeImage = async (file) => {
   } catch (error) {
     console.error("API request failed:", error);
     
-    // Return fallback data with embedded species info for web
+    if (!isDemoMode) {
+      // Same kinds as the desktop app's AnalysisError
+      throw toAnalysisError({
+        kind: error?.response ? 'http_status' : error?.code === 'ECONNABORTED' ? 'timeout' : 'network',
+        message: error?.response?.data?.detail || error?.message || String(error),
+        status: error?.response?.status,
+      });
+    }
+    
+    // Demo mode: return mock data with embedded species info
     const label = "Oak_Tree";
     
     return {
@@ -212,4 +244,62 @@ export const getAllSpecies = async () => {
     console.error("Failed to get all species:", error);
     return []; // Return empty array on failure
   }
-}; 
\ No newline at end of file
+}; 
+/**
+ * Full-text search over the species database (desktop app only)
+ * @param {string} query - Words to match against names, family, uses and notes
+ * @param {Object} options - `limit` and `offset` for pagination
+ * @returns {Promise<Object>} - `{ query, total, limit, offset, results }` with `<mark>`-highlighted fields
+ */
+export const searchSpecies = async (query, { limit = 20, offset = 0 } = {}) => {
+  if (!isTauri) {
+    throw new Error("Species search is only available in the desktop app");
+  }
+
+  const { invoke } = await import('@tauri-apps/api/tauri');
+  return invoke('search_species', { query, limit, offset });
+};
+
+/**
+ * Queryable wood anatomy, size and phenology of one species (desktop app only)
+ * @param {number} speciesId - Species id
+ * @returns {Promise<Object>} - Density and size ranges, porosity, growth rings, flowering/fruiting months
+ */
+export const getSpeciesAttributes = async (speciesId) => {
+  if (!isTauri) {
+    throw new Error("Species attributes are only available in the desktop app");
+  }
+
+  const { invoke } = await import('@tauri-apps/api/tauri');
+  return invoke('get_species_attributes', { speciesId });
+};
+
+/**
+ * Find species by attribute (desktop app only)
+ * @param {Object} filter - `{ all, any, limit, offset }`; entries are conditions such as
+ *   `{ field: 'density', min: 0.6, max: 0.8 }`, `{ field: 'xylemPorosity', anyOf: ['diffuse-porous'] }`,
+ *   `{ field: 'deciduous', value: true }`, `{ field: 'flowering', months: [4, 5] }`, or nested `{ all }` / `{ any }` groups
+ * @returns {Promise<Array>} - Matching species, by scientific name
+ */
+export const filterSpecies = async (filter = {}) => {
+  if (!isTauri) {
+    throw new Error("Species filtering is only available in the desktop app");
+  }
+
+  const { invoke } = await import('@tauri-apps/api/tauri');
+  return invoke('filter_species', { filter });
+};
+
+/**
+ * Multi-entry wood anatomy key (desktop app only). Pass every character observed so far.
+ * @param {Array} observations - e.g. `[{ character: 'xylemPorosity', state: 'diffuse-porous' }]`
+ * @returns {Promise<Object>} - `{ observations, candidates, suggestions }`, suggestions most discriminating first
+ */
+export const identifyByKey = async (observations = []) => {
+  if (!isTauri) {
+    throw new Error("The identification key is only available in the desktop app");
+  }
+
+  const { invoke } = await import('@tauri-apps/api/tauri');
+  return invoke('identify_by_key', { observations });
+};
@@ -6,7 +6,7 @@ import ImageUploader from './ImageUploader';
 import SpeciesCard from './SpeciesCard';
 import ApiErrorNotification from './ApiErrorNotification';
 import { checkApiAvailability } from '../proxy';
-import { analyzeImage, getSpeciesInfo, getAllSpecies } from '../api-client';
+import { analyzeImage, getSpeciesInfo, getAllSpecies, isDemoMode } from '../api-client';
 import ImagePreviewWrapper from './ImagePreviewWrapper';
 import Tooltip from './Tooltip';
 import useAnalysisStore from '../store/analysisStore';
@@ -112,6 +112,14 @@ function Dashboard({ onOpenMeasurementTool }) {
     }
   }, []);
   
+  // Species properties arrive as an object and uses as an array, either of which may be empty
+  const hasContent = (value) => {
+    if (!value) return false;
+    if (Array.isArray(value)) return value.length > 0;
+    if (typeof value === 'object') return Object.values(value).some(item => item != null);
+    return true;
+  };
+
   // Fallback function to get local species info when API is unavailable
   const getLocalSpeciesInfo = (label) => {
     console.log("Using local fallback data for:", label);
@@ -235,8 +243,8 @@ function Dashboard({ onOpenMeasurementTool }) {
               // Only add these fields if they don't exist in the API response
               common_name: analysisResult.speciesInfo.common_name || localData.common_name,
               family: analysisResult.speciesInfo.family || localData.family,
-              properties: analysisResult.speciesInfo.properties || localData.properties,
-              uses: analysisResult.speciesInfo.uses || localData.uses,
+              properties: hasContent(analysisResult.speciesInfo.properties) ? analysisResult.speciesInfo.properties : localData.properties,
+              uses: hasContent(analysisResult.speciesInfo.uses) ? analysisResult.speciesInfo.uses : localData.uses,
               distribution: analysisResult.speciesInfo.distribution || localData.distribution,
               habitat: analysisResult.speciesInfo.habitat || localData.habitat,
               conservation_status: analysisResult.speciesInfo.conservation_status || localData.conservation_status
@@ -289,17 +297,19 @@ function Dashboard({ onOpenMeasurementTool }) {
         }
       } catch (error) {
         console.error("Error processing species info:", error);
-        // In web mode, don't show error messages about API failure, just use fallback
-        if (isTauri) {
+        setApiAvailable(false);
+        
+        // Outside demo mode the failure is reported rather than replaced with a mock result
+        if (isTauri || !isDemoMode) {
           setError(`Analysis failed: ${error.message}`);
-          setShowApiError(true);
-        } else {
-          // Clear any previous error messages
-          setError(null);
+          if (isTauri) {
+            setShowApiError(true);
+          }
+          return;
         }
-        setApiAvailable(false);
         
-        // For web version, try to get a random species from our speciesData
+        // Demo mode: clear any previous error messages and show a random species from our speciesData
+        setError(null);
         const speciesKeys = Object.keys(speciesData);
         const randomKey = speciesKeys[Math.floor(Math.random() * speciesKeys.length)];
         
@@ -327,13 +337,13 @@ function Dashboard({ onOpenMeasurementTool }) {
       }
     } catch (error) {
       console.error('Error analyzing image:', error);
-      if (isTauri) {
+      if (isTauri || !isDemoMode) {
         setError(`Failed to analyze image: ${error.message || error}`);
       } else {
-        // In web version, don't show the error, just set to null
+        // Demo mode: don't show the error, just set to null
         setError(null);
         
-        // For web version, use a random entry from our speciesData
+        // Use a random entry from our speciesData
         const speciesKeys = Object.keys(speciesData);
         const randomKey = speciesKeys[Math.floor(Math.random() * speciesKeys.length)];
         
@@ -197,7 +197,7 @@ function SpeciesCard({ species, confidence, processingTime, image, onClear }) {
   const parseProperties = (props) => {
     if (!props) return null;
     
-    // If it's already an object, return it directly
+    // get_species_info sends an object, with null for unknown values
     if (typeof props === 'object' && props !== null) return props;
     
     // Try to parse as JSON if it's a string
@@ -265,7 +265,7 @@ function SpeciesCard({ species, confidence, processingTime, image, onClear }) {
   const parseCommonUses = (uses) => {
     if (!uses) return [];
     
-    // If it's already an array, return it directly
+    // get_species_info sends an array
     if (Array.isArray(uses)) return uses;
     
     // If it's a string, try multiple parsing strategies
@@ -624,24 +624,24 @@ function SpeciesCard({ species, confidence, processingTime, image, onClear }) {
       }
       
       // Growth Characteristics
-      if (properties && (properties.shade_tolerant !== undefined || properties.shade_intolerant !== undefined || properties.deciduous !== undefined)) {
+      if (properties && (properties.shade_tolerant != null || properties.shade_intolerant != null || properties.deciduous != null)) {
         propertiesHTML += `
           <div class="pdf-property-item">
             <h4>Growth Characteristics</h4>
             <ul class="text-2xs" style="padding-left: 12px; margin-top: 2px;">
-              ${properties.deciduous !== undefined ? `
+              ${properties.deciduous != null ? `
                 <li style="margin-bottom: 4px;">
                   <span style="font-weight: 600; display: inline-block; min-width: 70px;">Leaf Type:</span>
                   ${properties.deciduous ? "Deciduous" : "Evergreen"}
                 </li>
               ` : ''}
-              ${properties.shade_tolerant !== undefined ? `
+              ${properties.shade_tolerant != null ? `
                 <li style="margin-bottom: 4px;">
                   <span style="font-weight: 600; display: inline-block; min-width: 70px;">Shade Tolerant:</span>
                   ${properties.shade_tolerant ? "Yes" : "No"}
                 </li>
               ` : ''}
-              ${properties.shade_intolerant !== undefined ? `
+              ${properties.shade_intolerant != null ? `
                 <li>
                   <span style="font-weight: 600; display: inline-block; min-width: 70px;">Shade Intolerant:</span>
                   ${properties.shade_intolerant ? "Yes" : "No"}
@@ -923,26 +923,26 @@ function SpeciesCard({ species, confidence, processingTime, image, onClear }) {
               </motion.div>
             )}
             
-            {properties && (properties.shade_tolerant !== undefined || properties.shade_intolerant !== undefined || properties.deciduous !== undefined) && (
+            {properties && (properties.shade_tolerant != null || properties.shade_intolerant != null || properties.deciduous != null) && (
               <motion.div 
                 className="bg-green-50/30 dark:bg-gray-700/30 p-3 rounded shadow-sm border border-green-100/30 dark:border-gray-600/20"
                 whileHover={{ scale: 1.01, transition: { duration: 0.2 } }}
               >
                 <h4 className="text-sm font-semibold mb-1 text-gray-800 dark:text-gray-200">Growth Characteristics</h4>
                 <ul className="space-y-1 text-gray-700 dark:text-gray-300 text-sm">
-                  {properties.deciduous !== undefined && (
+                  {properties.deciduous != null && (
                     <li>
                       <span className="font-medium">Leaf Type:</span>{" "}
                       <span>{properties.deciduous ? "Deciduous" : "Evergreen"}</span>
                     </li>
                   )}
-                  {properties.shade_tolerant !== undefined && (
+                  {properties.shade_tolerant != null && (
                     <li>
                       <span className="font-medium">Shade Tolerant:</span>{" "}
                       <span>{properties.shade_tolerant ? "Yes" : "No"}</span>
                     </li>
                   )}
-                  {properties.shade_intolerant !== undefined && (
+                  {properties.shade_intolerant != null && (
                     <li>
                       <span className="font-medium">Shade Intolerant:</span>{" "}
                       <span>{properties.shade_intolerant ? "Yes" : "No"}</span>
@@ -48,8 +48,8 @@ export default defineConfig({
   
   // To make use of `TAURI_DEBUG`, `TAURI_PLATFORM`, `TAURI_ARCH`, `TAURI_FAMILY`,
   // `TAURI_PLATFORM_VERSION`, `TAURI_PLATFORM_TYPE` and `TAURI_DEBUG`
-  // env variables
-  envPrefix: ['VITE_', 'TAURI_'],
+  // env variables, and `TREESCOPE_DEMO_MODE`, which also enables the desktop app's demo backend
+  envPrefix: ['VITE_', 'TAURI_', 'TREESCOPE_DEMO_MODE'],
   
   build: {
     // Tauri supports es2021